@@ -1,15 +1,84 @@
+use crate::glob_pattern::glob_capture;
+use serde_json::Value;
 use std::collections::HashSet;
 
+/// The placeholder capturing the tenant in accepted issuer (or audience) patterns
+pub static TENANT_PLACEHOLDER: &str = "{tenant}";
+
+/// The result of a successful match of a claim against the accepted values
 #[derive(Debug, Clone, Default, Eq, PartialEq)]
-pub struct AcceptedClaims(HashSet<String>, String);
+pub struct ClaimMatch {
+    /// The segment captured by the `{tenant}` placeholder (if any)
+    pub tenant: Option<String>,
+}
+
+/// The accepted values of a claim. Values containing wildcards (`*` or `?`) or the
+/// `{tenant}` placeholder are matched as patterns, the others must match exactly.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AcceptedClaims {
+    values: HashSet<String>,
+    patterns: Vec<String>,
+    claim_name: String,
+}
+
+fn is_pattern(value: &str) -> bool {
+    value.contains(['*', '?']) || value.contains(TENANT_PLACEHOLDER)
+}
 
 impl AcceptedClaims {
     pub fn new(accepted_values: HashSet<String>, claim_name: String) -> Self {
-        Self(accepted_values, claim_name)
+        let (patterns, values): (HashSet<_>, HashSet<_>) =
+            accepted_values.into_iter().partition(|v| is_pattern(v));
+        let mut patterns: Vec<_> = patterns.into_iter().collect();
+        patterns.sort();
+
+        Self {
+            values,
+            patterns,
+            claim_name,
+        }
     }
 
-    pub fn accepted_values(&self) -> Vec<String> {
-        self.0.iter().cloned().collect()
+    fn find_value_match(&self, value: &str) -> Option<ClaimMatch> {
+        if self.values.contains(value) {
+            return Some(ClaimMatch::default());
+        }
+        self.patterns.iter().find_map(|pattern| {
+            glob_capture(pattern, value, TENANT_PLACEHOLDER).map(|tenant| ClaimMatch {
+                tenant: tenant.map(str::to_string),
+            })
+        })
+    }
+
+    /// Returns `true` if no value is configured (any claim is accepted)
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Matches the claim (a string or an array of strings) against the accepted values
+    /// and patterns. If nothing is configured, any claim is accepted.
+    pub fn find_match(&self, claim: &Value) -> Option<ClaimMatch> {
+        if self.is_empty() {
+            return Some(ClaimMatch::default());
+        }
+        match claim {
+            Value::String(value) => self.find_value_match(value),
+            Value::Array(values) => values
+                .iter()
+                .filter_map(Value::as_str)
+                .find_map(|value| self.find_value_match(value)),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the claim (a string or an array of strings) matches one of the accepted
+    /// values. If no value is configured, any claim is accepted.
+    pub fn accepts(&self, claim: &Value) -> bool {
+        self.find_match(claim).is_some()
+    }
+
+    pub fn claim_name(&self) -> &str {
+        &self.claim_name
     }
 
     pub fn from_comma_separated_values(comma_separated_values: &str, claim_name: String) -> Self {
@@ -27,6 +96,7 @@ impl AcceptedClaims {
 mod tests {
 
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_initialize_from_comma_separated_values() {
@@ -48,4 +118,44 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn it_should_check_if_a_claim_is_accepted() {
+        let accepted_claims =
+            AcceptedClaims::from_comma_separated_values("app-1, app-2", "aud".to_string());
+        assert!(accepted_claims.accepts(&json!("app-1")));
+        assert!(accepted_claims.accepts(&json!(["app-3", "app-2"])));
+        assert!(!accepted_claims.accepts(&json!("app-3")));
+        assert!(!accepted_claims.accepts(&json!(null)));
+
+        let accepted_claims = AcceptedClaims::from_comma_separated_values("", "aud".to_string());
+        assert!(accepted_claims.accepts(&json!(null)));
+    }
+
+    #[test]
+    fn it_should_match_patterns_and_capture_the_tenant() {
+        let accepted_claims = AcceptedClaims::from_comma_separated_values(
+            "https://example.com, https://login.microsoftonline.com/{tenant}/v2.0, https://*.auth0.com/",
+            "iss".to_string(),
+        );
+        assert_eq!(
+            accepted_claims.find_match(&json!("https://example.com")),
+            Some(ClaimMatch::default())
+        );
+        assert_eq!(
+            accepted_claims.find_match(&json!("https://login.microsoftonline.com/1234/v2.0")),
+            Some(ClaimMatch {
+                tenant: Some("1234".to_string())
+            })
+        );
+        assert_eq!(
+            accepted_claims.find_match(&json!(["https://other.com", "https://acme.auth0.com/"])),
+            Some(ClaimMatch::default())
+        );
+        assert_eq!(
+            accepted_claims.find_match(&json!("https://login.microsoftonline.com/1234/v1.0")),
+            None
+        );
+        assert!(!accepted_claims.accepts(&json!("https://example.com.evil.com")));
+    }
 }
@@ -0,0 +1,169 @@
+use crate::{
+    accepted_claims::{AcceptedClaims, TENANT_PLACEHOLDER},
+    glob_pattern::glob_capture,
+};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The claims carrying the client ID, in order (`azp` is the standard one, `client_id` is
+/// defined by RFC 9068 and `cid` is used by Okta)
+pub static DEFAULT_CLIENT_ID_CLAIMS: &str = "azp, client_id, cid";
+
+#[derive(Debug, Error)]
+pub enum ClientIdError {
+    #[error("Invalid client ID claim override '{0}'. Expected format: 'issuer=claim'")]
+    MalformedOverride(String),
+    #[error("The token does not contain a client ID (expected one of: {0})")]
+    MissingClientId(String),
+    #[error("The client '{0}' is not accepted")]
+    NotAccepted(String),
+}
+
+/// Restricts the OAuth clients allowed to call the API
+#[derive(Debug, Clone, Default)]
+pub struct AcceptedClientIds {
+    accepted_values: AcceptedClaims,
+    claims: Vec<String>,
+    /// The claim to use for specific issuers (issuers can be patterns, like the accepted issuers)
+    issuer_claims: Vec<(String, String)>,
+}
+
+impl AcceptedClientIds {
+    pub fn new(accepted_values: AcceptedClaims, claims: Vec<String>) -> Self {
+        Self {
+            accepted_values,
+            claims,
+            issuer_claims: Vec::new(),
+        }
+    }
+
+    pub fn from_comma_separated_values(comma_separated_values: &str, claims: &str) -> Self {
+        Self::new(
+            AcceptedClaims::from_comma_separated_values(comma_separated_values, "azp".to_string()),
+            claims
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        )
+    }
+
+    /// Sets the client ID claim for specific issuers, given as `issuer=claim` pairs
+    /// separated by commas (e.g. `https://login.microsoftonline.com/*/v2.0=appid`)
+    pub fn with_issuer_claims(
+        mut self,
+        comma_separated_values: &str,
+    ) -> Result<Self, ClientIdError> {
+        self.issuer_claims = comma_separated_values
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| match s.rsplit_once('=') {
+                Some((issuer, claim)) if !issuer.trim().is_empty() && !claim.trim().is_empty() => {
+                    Ok((issuer.trim().to_string(), claim.trim().to_string()))
+                }
+                _ => Err(ClientIdError::MalformedOverride(s.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.accepted_values.is_empty()
+    }
+
+    fn client_id_claims(&self, issuer: &str) -> Vec<&str> {
+        match self
+            .issuer_claims
+            .iter()
+            .find(|(pattern, _)| glob_capture(pattern, issuer, TENANT_PLACEHOLDER).is_some())
+        {
+            Some((_, claim)) => vec![claim.as_str()],
+            None => self.claims.iter().map(String::as_str).collect(),
+        }
+    }
+
+    pub fn validate(&self, claims: &Value) -> Result<(), ClientIdError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let client_id_claims = self.client_id_claims(claims["iss"].as_str().unwrap_or_default());
+        let Some(client_id) = client_id_claims
+            .iter()
+            .find_map(|claim| claims.get(claim).filter(|value| !value.is_null()))
+        else {
+            return Err(ClientIdError::MissingClientId(client_id_claims.join(", ")));
+        };
+        if !self.accepted_values.accepts(client_id) {
+            return Err(ClientIdError::NotAccepted(client_id.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_should_accept_any_client_by_default() {
+        let accepted_client_ids =
+            AcceptedClientIds::from_comma_separated_values("", DEFAULT_CLIENT_ID_CLAIMS);
+        assert!(!accepted_client_ids.is_enabled());
+        assert!(accepted_client_ids
+            .validate(&json!({"sub": "user123"}))
+            .is_ok());
+    }
+
+    #[test]
+    fn it_should_validate_the_client_id() {
+        let accepted_client_ids = AcceptedClientIds::from_comma_separated_values(
+            "app-1, app-2",
+            DEFAULT_CLIENT_ID_CLAIMS,
+        );
+        assert!(accepted_client_ids
+            .validate(&json!({"azp": "app-1"}))
+            .is_ok());
+        assert!(accepted_client_ids
+            .validate(&json!({"client_id": "app-2"}))
+            .is_ok());
+        assert!(accepted_client_ids
+            .validate(&json!({"cid": "app-1"}))
+            .is_ok());
+        // azp takes precedence
+        assert!(matches!(
+            accepted_client_ids.validate(&json!({"azp": "app-3", "client_id": "app-1"})),
+            Err(ClientIdError::NotAccepted(_))
+        ));
+        assert!(matches!(
+            accepted_client_ids.validate(&json!({"sub": "user123"})),
+            Err(ClientIdError::MissingClientId(_))
+        ));
+    }
+
+    #[test]
+    fn it_should_use_the_claim_configured_for_the_issuer() {
+        let accepted_client_ids =
+            AcceptedClientIds::from_comma_separated_values("app-1", DEFAULT_CLIENT_ID_CLAIMS)
+                .with_issuer_claims("https://login.microsoftonline.com/*/v2.0=appid")
+                .unwrap();
+        assert!(accepted_client_ids
+            .validate(&json!({"iss": "https://login.microsoftonline.com/acme/v2.0", "appid": "app-1", "azp": "app-2"}))
+            .is_ok());
+        assert!(matches!(
+            accepted_client_ids.validate(
+                &json!({"iss": "https://login.microsoftonline.com/acme/v2.0", "azp": "app-1"})
+            ),
+            Err(ClientIdError::MissingClientId(_))
+        ));
+        assert!(accepted_client_ids
+            .validate(&json!({"iss": "https://idp.example.com", "azp": "app-1"}))
+            .is_ok());
+
+        assert!(matches!(
+            AcceptedClientIds::default().with_issuer_claims("https://idp.example.com"),
+            Err(ClientIdError::MalformedOverride(_))
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    decision::{Decision, DenyReason},
+    models::TokenAuthorizerResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The version of the audit event schema. It must be increased on every breaking change.
+const AUDIT_EVENT_VERSION: u32 = 1;
+
+/// An audit record of an authorization decision.
+/// The schema is stable: fields can be added but never renamed or removed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    log_type: &'static str,
+    version: u32,
+    pub timestamp: String,
+    pub request_id: String,
+    pub principal_id: Option<String>,
+    pub issuer: Option<String>,
+    pub subject: Option<String>,
+    pub client_id: Option<String>,
+    pub jti: Option<String>,
+    pub method_arn: String,
+    pub decision: &'static str,
+    pub reason: Option<&'static str>,
+    pub rule: Option<String>,
+    pub policy_hash: String,
+}
+
+impl AuditEvent {
+    pub fn new(
+        decision: &Decision,
+        request_id: &str,
+        method_arn: &str,
+        response: &TokenAuthorizerResponse,
+    ) -> Self {
+        let policy = serde_json::to_string(&response.policy_document).unwrap();
+        let policy_hash: String = Sha256::digest(policy.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Self {
+            log_type: "audit",
+            version: AUDIT_EVENT_VERSION,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            request_id: request_id.to_string(),
+            principal_id: decision
+                .deny_reason
+                .is_none()
+                .then(|| response.principal_id.clone()),
+            issuer: decision.issuer.clone(),
+            subject: decision.subject.clone(),
+            client_id: decision.client_id.clone(),
+            jti: decision.jti.clone(),
+            method_arn: method_arn.to_string(),
+            decision: decision.effect(),
+            reason: decision.deny_reason.as_ref().map(DenyReason::name),
+            rule: match &decision.deny_reason {
+                Some(DenyReason::CelRejected { rule }) => rule.clone(),
+                _ => None,
+            },
+            policy_hash: format!("sha256:{}", policy_hash),
+        }
+    }
+}
+
+/// Emits audit events as JSON lines on stdout, bypassing the logger so that they are
+/// not affected by the log level and can be routed by a subscription filter on `logType`.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    enabled: bool,
+}
+
+impl AuditLog {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    pub fn emit(&self, event: &AuditEvent) {
+        if self.enabled {
+            println!("{}", serde_json::to_string(event).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_should_create_an_audit_event_for_an_allowed_request() {
+        let decision = Decision {
+            issuer: Some("https://example.com".to_string()),
+            subject: Some("user-123".to_string()),
+            client_id: Some("some-client".to_string()),
+            jti: Some("some-jti".to_string()),
+            ..Default::default()
+        };
+        let response = TokenAuthorizerResponse::allow("john", &json!({"sub": "user-123"}));
+
+        let event = AuditEvent::new(&decision, "some-request-id", "some_arn", &response);
+        let event = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(event["logType"], "audit");
+        assert_eq!(event["version"], 1);
+        assert!(event["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(event["requestId"], "some-request-id");
+        assert_eq!(event["principalId"], "john");
+        assert_eq!(event["issuer"], "https://example.com");
+        assert_eq!(event["subject"], "user-123");
+        assert_eq!(event["clientId"], "some-client");
+        assert_eq!(event["jti"], "some-jti");
+        assert_eq!(event["methodArn"], "some_arn");
+        assert_eq!(event["decision"], "Allow");
+        assert_eq!(event["reason"], json!(null));
+        assert_eq!(event["rule"], json!(null));
+        assert!(event["policyHash"].as_str().unwrap().starts_with("sha256:"));
+    }
+
+    #[test]
+    fn it_should_create_an_audit_event_for_a_denied_request() {
+        let decision = Decision {
+            deny_reason: Some(DenyReason::CelRejected {
+                rule: Some("email-verified".to_string()),
+            }),
+            ..Default::default()
+        };
+        let response = TokenAuthorizerResponse::deny("some_arn");
+
+        let event = AuditEvent::new(&decision, "some-request-id", "some_arn", &response);
+
+        assert_eq!(event.decision, "Deny");
+        assert_eq!(event.principal_id, None);
+        assert_eq!(event.reason, Some("CelRejected"));
+        assert_eq!(event.rule, Some("email-verified".to_string()));
+
+        // the policy hash only depends on the policy
+        let other_event = AuditEvent::new(
+            &Decision::default(),
+            "other-request-id",
+            "some_arn",
+            &TokenAuthorizerResponse::deny("some_arn"),
+        );
+        assert_eq!(event.policy_hash, other_event.policy_hash);
+        let allow_event = AuditEvent::new(
+            &Decision::default(),
+            "other-request-id",
+            "some_arn",
+            &TokenAuthorizerResponse::allow("john", &json!({})),
+        );
+        assert_ne!(event.policy_hash, allow_event.policy_hash);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::glob_pattern::glob_match;
+use cel_interpreter::{objects::Key, Context, ExecutionError, FunctionContext, Value};
+use chrono::Utc;
+use ipnet::IpNet;
+use std::{net::IpAddr, sync::Arc};
+
+type Result<T> = std::result::Result<T, ExecutionError>;
+
+/// Registers the custom functions available to CEL expressions:
+///
+/// - `now()`: the current time as seconds since the Unix epoch (comparable with `exp`, `iat` and `nbf`)
+/// - `tokenAge(claims)`: the number of seconds elapsed since the token was issued (`iat`)
+/// - `expiresIn(claims)`: the number of seconds until the token expires (`exp`)
+/// - `scopes(claims)`: the list of scopes from the `scope` (space-separated) or `scp` claims
+/// - `hasScope(claims, scope)`: whether the token has the given scope
+/// - `inCidr(ip, cidr)`: whether the IP address is part of the given CIDR block
+/// - `glob(value, pattern)`: whether the value matches the glob pattern (`*` and `?` wildcards)
+pub fn register(context: &mut Context) {
+    context.add_function("now", now);
+    context.add_function("tokenAge", token_age);
+    context.add_function("expiresIn", expires_in);
+    context.add_function("scopes", scopes);
+    context.add_function("hasScope", has_scope);
+    context.add_function("inCidr", in_cidr);
+    context.add_function("glob", glob);
+}
+
+fn now() -> Result<Value> {
+    Ok(Value::Int(Utc::now().timestamp()))
+}
+
+fn token_age(ftx: &FunctionContext, claims: Value) -> Result<Value> {
+    let iat = get_timestamp_claim(ftx, &claims, "iat")?;
+    Ok(Value::Int(Utc::now().timestamp() - iat))
+}
+
+fn expires_in(ftx: &FunctionContext, claims: Value) -> Result<Value> {
+    let exp = get_timestamp_claim(ftx, &claims, "exp")?;
+    Ok(Value::Int(exp - Utc::now().timestamp()))
+}
+
+fn scopes(claims: Value) -> Result<Value> {
+    Ok(Value::List(Arc::new(
+        get_scopes(&claims).into_iter().map(Value::from).collect(),
+    )))
+}
+
+fn has_scope(claims: Value, scope: Arc<String>) -> Result<Value> {
+    Ok(Value::Bool(get_scopes(&claims).contains(scope.as_ref())))
+}
+
+fn in_cidr(ftx: &FunctionContext, ip: Arc<String>, cidr: Arc<String>) -> Result<Value> {
+    let cidr: IpNet = cidr
+        .parse()
+        .map_err(|e| ftx.error(format!("'{}' is not a valid CIDR block: {}", cidr, e)))?;
+    // an invalid (or missing) IP address is never part of a CIDR block
+    Ok(Value::Bool(
+        ip.parse::<IpAddr>().is_ok_and(|ip| cidr.contains(&ip)),
+    ))
+}
+
+fn glob(value: Arc<String>, pattern: Arc<String>) -> Result<Value> {
+    Ok(Value::Bool(glob_match(&pattern, &value)))
+}
+
+fn get_claim<'a>(claims: &'a Value, name: &str) -> Option<&'a Value> {
+    match claims {
+        Value::Map(map) => map.map.get(&Key::String(Arc::new(name.to_string()))),
+        _ => None,
+    }
+}
+
+fn get_timestamp_claim(ftx: &FunctionContext, claims: &Value, name: &str) -> Result<i64> {
+    match get_claim(claims, name) {
+        Some(Value::Int(i)) => Ok(*i),
+        Some(Value::UInt(u)) => Ok(*u as i64),
+        Some(Value::Float(f)) => Ok(*f as i64),
+        _ => Err(ftx.error(format!("missing or invalid '{}' claim", name))),
+    }
+}
+
+fn get_scopes(claims: &Value) -> Vec<String> {
+    let mut scopes = vec![];
+    for name in ["scope", "scp"] {
+        match get_claim(claims, name) {
+            Some(Value::String(s)) => scopes.extend(s.split_whitespace().map(str::to_string)),
+            Some(Value::List(list)) => scopes.extend(list.iter().filter_map(|v| match v {
+                Value::String(s) => Some(s.to_string()),
+                _ => None,
+            })),
+            _ => {}
+        }
+    }
+    scopes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel_interpreter::Program;
+    use serde_json::json;
+
+    fn eval(expression: &str, claims: serde_json::Value) -> Result<Value> {
+        let mut context = Context::default();
+        register(&mut context);
+        context.add_variable("claims", claims).unwrap();
+        Program::compile(expression).unwrap().execute(&context)
+    }
+
+    #[test]
+    fn it_should_return_the_current_time() {
+        let now = Utc::now().timestamp();
+        assert_eq!(
+            eval("now() >= claims.before", json!({"before": now})),
+            Ok(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn it_should_return_the_token_age() {
+        let iat = Utc::now().timestamp() - 600;
+        assert_eq!(
+            eval(
+                "tokenAge(claims) >= 600 && tokenAge(claims) < 660",
+                json!({"iat": iat})
+            ),
+            Ok(Value::Bool(true))
+        );
+        assert!(eval("tokenAge(claims) < 300", json!({})).is_err());
+    }
+
+    #[test]
+    fn it_should_return_the_time_until_expiration() {
+        let exp = Utc::now().timestamp() + 3600;
+        assert_eq!(
+            eval(
+                "expiresIn(claims) > 3500 && expiresIn(claims) <= 3600",
+                json!({"exp": exp})
+            ),
+            Ok(Value::Bool(true))
+        );
+        assert!(eval("expiresIn(claims) > 0", json!({"exp": "soon"})).is_err());
+    }
+
+    #[test]
+    fn it_should_split_scopes() {
+        assert_eq!(
+            eval(
+                r#"scopes(claims) == ["read:users", "write:users"]"#,
+                json!({"scope": "read:users  write:users"})
+            ),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval(r#"scopes(claims) == ["admin"]"#, json!({"scp": ["admin"]})),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("size(scopes(claims)) == 0", json!({})),
+            Ok(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn it_should_check_scopes() {
+        let claims = json!({"scope": "openid read:users"});
+        assert_eq!(
+            eval(r#"hasScope(claims, "read:users")"#, claims.clone()),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval(r#"hasScope(claims, "read")"#, claims),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            eval(r#"hasScope(claims, "admin")"#, json!({"scp": "admin"})),
+            Ok(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn it_should_check_cidr_blocks() {
+        let claims = json!({"ip": "10.1.2.3", "ipv6": "2001:db8::1"});
+        assert_eq!(
+            eval(r#"inCidr(claims.ip, "10.0.0.0/8")"#, claims.clone()),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval(r#"inCidr(claims.ip, "192.168.0.0/16")"#, claims.clone()),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            eval(r#"inCidr(claims.ipv6, "2001:db8::/32")"#, claims.clone()),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval(r#"inCidr("not-an-ip", "10.0.0.0/8")"#, claims.clone()),
+            Ok(Value::Bool(false))
+        );
+        assert!(eval(r#"inCidr(claims.ip, "not-a-cidr")"#, claims).is_err());
+    }
+
+    #[test]
+    fn it_should_match_glob_patterns() {
+        let claims = json!({"iss": "https://login.example.com/tenant-a/v2.0"});
+        assert_eq!(
+            eval(
+                r#"glob(claims.iss, "https://login.example.com/*/v2.0")"#,
+                claims.clone()
+            ),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval(r#"glob(claims.iss, "https://other.example.com/*")"#, claims),
+            Ok(Value::Bool(false))
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use crate::{
+    cel_validation::CelValidator, context_enrichment::ContextEnrichment, models::RequestMetadata,
+    principalid_claims::PrincipalIDClaims,
+};
+use jsonwebtoken::Header;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, path::Path};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CelSelfTestError {
+    #[error("Failed to load CEL self-test fixtures: {0}")]
+    LoadError(String),
+    #[error("CEL self-test failed:\n{}", .0.join("\n"))]
+    Failures(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expectation {
+    Allow,
+    Deny,
+}
+
+/// A sample token (and request) with the expected outcome of the configured CEL expressions
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CelFixture {
+    name: String,
+    #[serde(default)]
+    header: Option<Header>,
+    claims: Value,
+    #[serde(default)]
+    request: RequestMetadata,
+    expect: Expectation,
+    #[serde(rename = "principalId", default)]
+    principal_id: Option<String>,
+    /// The expected values of some context keys (computed by `CONTEXT_CLAIMS` and `CONTEXT_CEL`)
+    #[serde(default)]
+    context: Option<HashMap<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CelFixturesFile {
+    fixtures: Vec<CelFixture>,
+}
+
+/// Evaluates the configured CEL expressions against a set of fixtures at startup,
+/// so that broken expressions (e.g. typos in claim names) are caught before serving requests.
+#[derive(Debug, Clone, Default)]
+pub struct CelSelfTest(Vec<CelFixture>);
+
+impl CelSelfTest {
+    /// Loads the fixtures from a JSON or YAML file
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, CelSelfTestError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| CelSelfTestError::LoadError(format!("'{}': {}", path.display(), e)))?;
+        Self::load_from_str(&content)
+    }
+
+    /// Parses the fixtures from a JSON or YAML string (YAML is a superset of JSON)
+    pub fn load_from_str(content: &str) -> Result<Self, CelSelfTestError> {
+        let fixtures_file: CelFixturesFile = serde_yaml::from_str(content)
+            .map_err(|e| CelSelfTestError::LoadError(e.to_string()))?;
+        Ok(Self(fixtures_file.fixtures))
+    }
+
+    pub fn extend(&mut self, other: CelSelfTest) {
+        self.0.extend(other.0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn run(
+        &self,
+        cel_validator: &CelValidator,
+        principal_id_claims: &PrincipalIDClaims,
+        context_enrichment: &ContextEnrichment,
+    ) -> Result<(), CelSelfTestError> {
+        let mut failures = vec![];
+
+        for fixture in &self.0 {
+            let header = fixture.header.as_ref();
+            let result = cel_validator.validate(header, &fixture.claims, &fixture.request);
+            match (fixture.expect, result) {
+                (Expectation::Allow, Err(e)) => failures.push(format!(
+                    "  - '{}': expected allow, got deny ({})",
+                    fixture.name, e
+                )),
+                (Expectation::Deny, Ok(())) => {
+                    failures.push(format!("  - '{}': expected deny, got allow", fixture.name))
+                }
+                _ => {}
+            }
+
+            if let Some(expected_principal_id) = &fixture.principal_id {
+                match principal_id_claims.get_principal_id(
+                    header,
+                    &fixture.claims,
+                    &fixture.request,
+                ) {
+                    Ok(principal_id) if &principal_id == expected_principal_id => {}
+                    Ok(principal_id) => failures.push(format!(
+                        "  - '{}': expected principal ID '{}', got '{}'",
+                        fixture.name, expected_principal_id, principal_id
+                    )),
+                    Err(e) => failures.push(format!(
+                        "  - '{}': failed to compute the principal ID ({})",
+                        fixture.name, e
+                    )),
+                }
+            }
+
+            // the context is only computed for allowed requests
+            if fixture.expect == Expectation::Allow {
+                match context_enrichment.build(header, &fixture.claims, &fixture.request) {
+                    Ok(context) => {
+                        let mut expected_context: Vec<_> =
+                            fixture.context.iter().flatten().collect();
+                        expected_context.sort_by_key(|(key, _)| *key);
+                        for (key, expected_value) in expected_context {
+                            match context.get(key) {
+                                Some(value) if value == expected_value => {}
+                                value => failures.push(format!(
+                                    "  - '{}': expected context '{}' to be {}, got {}",
+                                    fixture.name,
+                                    key,
+                                    expected_value,
+                                    value.unwrap_or(&Value::Null)
+                                )),
+                            }
+                        }
+                    }
+                    Err(e) => failures.push(format!(
+                        "  - '{}': failed to compute the context ({})",
+                        fixture.name, e
+                    )),
+                }
+            }
+        }
+
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(CelSelfTestError::Failures(failures)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FIXTURES: &str = r#"
+        fixtures:
+          - name: verified user
+            claims: { sub: user123, email_verified: true }
+            expect: allow
+            principalId: user123
+          - name: unverified user
+            claims: { sub: user456, email_verified: false }
+            expect: deny
+    "#;
+
+    fn make_principal_id_claims() -> PrincipalIDClaims {
+        PrincipalIDClaims::from_comma_separated_values("sub", "unknown".to_string())
+    }
+
+    #[test]
+    fn it_should_pass_when_all_fixtures_match() {
+        let self_test = CelSelfTest::load_from_str(FIXTURES).unwrap();
+        assert_eq!(self_test.len(), 2);
+        let cel_validator: CelValidator = "claims.email_verified == true".parse().unwrap();
+        assert!(self_test
+            .run(
+                &cel_validator,
+                &make_principal_id_claims(),
+                &ContextEnrichment::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn it_should_fail_when_an_expression_has_a_typo() {
+        let self_test = CelSelfTest::load_from_str(FIXTURES).unwrap();
+        let cel_validator: CelValidator = "claims.emial_verified == true".parse().unwrap();
+        let result = self_test.run(
+            &cel_validator,
+            &make_principal_id_claims(),
+            &ContextEnrichment::default(),
+        );
+        match result {
+            Err(CelSelfTestError::Failures(failures)) => {
+                assert_eq!(failures.len(), 1);
+                assert!(failures[0].contains("'verified user': expected allow, got deny"));
+            }
+            _ => panic!("Expected the self-test to fail"),
+        }
+    }
+
+    #[test]
+    fn it_should_fail_when_a_deny_fixture_is_allowed() {
+        let self_test = CelSelfTest::load_from_str(FIXTURES).unwrap();
+        let cel_validator = CelValidator::default();
+        let result = self_test.run(
+            &cel_validator,
+            &make_principal_id_claims(),
+            &ContextEnrichment::default(),
+        );
+        assert!(
+            matches!(result, Err(CelSelfTestError::Failures(failures)) if failures[0].contains("'unverified user': expected deny, got allow"))
+        );
+    }
+
+    #[test]
+    fn it_should_fail_when_the_principal_id_does_not_match() {
+        let self_test = CelSelfTest::load_from_str(FIXTURES).unwrap();
+        let cel_validator: CelValidator = "claims.email_verified == true".parse().unwrap();
+        let principal_id_claims =
+            make_principal_id_claims().with_cel(r#""user:" + claims.sub"#.parse().unwrap());
+        let result = self_test.run(
+            &cel_validator,
+            &principal_id_claims,
+            &ContextEnrichment::default(),
+        );
+        assert!(
+            matches!(result, Err(CelSelfTestError::Failures(failures)) if failures[0].contains("expected principal ID 'user123', got 'user:user123'"))
+        );
+    }
+
+    #[test]
+    fn it_should_use_header_and_request_from_fixtures() {
+        let self_test = CelSelfTest::load_from_str(
+            r#"{"fixtures": [{
+                "name": "from the VPN",
+                "header": {"alg": "RS256", "kid": "key1"},
+                "claims": {},
+                "request": {"sourceIp": "10.0.0.1"},
+                "expect": "allow"
+            }]}"#,
+        )
+        .unwrap();
+        let cel_validator: CelValidator =
+            r#"header.kid == "key1" && inCidr(request.sourceIp, "10.0.0.0/8")"#
+                .parse()
+                .unwrap();
+        assert!(self_test
+            .run(
+                &cel_validator,
+                &make_principal_id_claims(),
+                &ContextEnrichment::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn it_should_evaluate_the_context() {
+        let self_test = CelSelfTest::load_from_str(
+            r#"{"fixtures": [{
+                "name": "tenant user",
+                "claims": {"sub": "user123", "tenant": "acme"},
+                "expect": "allow",
+                "context": {"tenantId": "acme", "plan": "free"}
+            }]}"#,
+        )
+        .unwrap();
+        let context_enrichment = |expression: &str| {
+            ContextEnrichment::default()
+                .with_claims(vec!["tenant->tenantId".parse().unwrap()])
+                .with_cel(expression.parse().unwrap())
+        };
+
+        assert!(self_test
+            .run(
+                &CelValidator::default(),
+                &make_principal_id_claims(),
+                &context_enrichment(r#"{"plan": "free"}"#)
+            )
+            .is_ok());
+        let result = self_test.run(
+            &CelValidator::default(),
+            &make_principal_id_claims(),
+            &context_enrichment(r#"{"plan": "pro"}"#),
+        );
+        assert!(
+            matches!(&result, Err(CelSelfTestError::Failures(failures)) if failures[0].contains(r#"expected context 'plan' to be "free", got "pro""#)),
+            "{:?}",
+            result
+        );
+        // a typo in the context expression breaks every allowed request
+        let result = self_test.run(
+            &CelValidator::default(),
+            &make_principal_id_claims(),
+            &context_enrichment(r#"{"plan": claims.plna}"#),
+        );
+        assert!(
+            matches!(&result, Err(CelSelfTestError::Failures(failures)) if failures[0].contains("failed to compute the context")),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn it_should_fail_to_load_invalid_fixtures() {
+        let result = CelSelfTest::load_from_str(
+            r#"{"fixtures": [{"name": "missing expectation", "claims": {}}]}"#,
+        );
+        assert!(matches!(result, Err(CelSelfTestError::LoadError(_))));
+    }
+}
@@ -1,6 +1,9 @@
+use crate::{cel_functions, glob_pattern::route_match, models::RequestMetadata};
 use cel_interpreter::{Context, Program, Value};
 use jsonwebtoken::Header;
+use serde::Deserialize;
 use serde_json::Value as JsonValue;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 use thiserror::Error;
@@ -15,58 +18,208 @@ pub enum CelValidationError {
     NonBooleanResult,
     #[error("Failed to convert token data to CEL value: {0}")]
     ConversionError(String),
+    #[error("Failed to load CEL rules file: {0}")]
+    RulesFileError(String),
+    #[error("CEL expression returned an unexpected value: {0}")]
+    UnexpectedResult(String),
+    #[error("CEL rule '{rule}' ({description}) failed: {source}")]
+    RuleFailed {
+        rule: String,
+        description: String,
+        source: Box<CelValidationError>,
+    },
+}
+
+/// What to do when a CEL rule does not pass
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CelRuleSeverity {
+    /// The token is rejected
+    #[default]
+    Deny,
+    /// The failure is logged but the token is not rejected
+    Log,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CelRulesFile {
+    rules: Vec<CelRuleDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CelRuleDefinition {
+    name: String,
+    #[serde(default)]
+    description: String,
+    expression: String,
+    #[serde(default)]
+    routes: Vec<String>,
+    #[serde(default)]
+    severity: CelRuleSeverity,
+}
+
+/// A named CEL expression, optionally scoped to a set of routes.
+/// Routes are glob patterns in the form `"<METHOD> <path>"` (e.g. `"GET /admin/*"`)
+/// or just `"<path>"` to match any method.
+#[derive(Debug, Clone)]
+pub struct CelRule {
+    pub name: String,
+    pub description: String,
+    pub routes: Vec<String>,
+    pub severity: CelRuleSeverity,
+    expression: String,
+    program: Arc<Program>,
+}
+
+impl CelRule {
+    pub fn new(
+        name: String,
+        description: String,
+        expression: &str,
+        routes: Vec<String>,
+        severity: CelRuleSeverity,
+    ) -> Result<Self, CelValidationError> {
+        let program = Program::compile(expression)
+            .map_err(|e| CelValidationError::CompileError(format!("rule '{}': {}", name, e)))?;
+
+        Ok(Self {
+            name,
+            description,
+            routes,
+            severity,
+            expression: expression.to_string(),
+            program: Arc::new(program),
+        })
+    }
+
+    /// Loads a list of rules from a JSON or YAML file
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Vec<Self>, CelValidationError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            CelValidationError::RulesFileError(format!("'{}': {}", path.display(), e))
+        })?;
+        Self::load_from_str(&content)
+    }
+
+    /// Parses a list of rules from a JSON or YAML string (YAML is a superset of JSON)
+    pub fn load_from_str(content: &str) -> Result<Vec<Self>, CelValidationError> {
+        let rules_file: CelRulesFile = serde_yaml::from_str(content)
+            .map_err(|e| CelValidationError::RulesFileError(e.to_string()))?;
+
+        rules_file
+            .rules
+            .into_iter()
+            .map(|rule| {
+                Self::new(
+                    rule.name,
+                    rule.description,
+                    &rule.expression,
+                    rule.routes,
+                    rule.severity,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns `true` if the outcome of the rule depends on the request: the rule is scoped
+    /// to some routes or its expression reads the `request` variable. Log-only rules never
+    /// change the decision.
+    pub fn is_request_dependent(&self) -> bool {
+        self.severity == CelRuleSeverity::Deny
+            && (!self.routes.is_empty() || self.program.references().has_variable("request"))
+    }
+
+    pub fn applies_to(&self, method: &str, path: &str) -> bool {
+        self.routes.is_empty()
+            || self
+                .routes
+                .iter()
+                .any(|route| route_match(route, method, path))
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct CelValidator {
     program: Option<Arc<Program>>,
     expression: String,
+    rules: Vec<CelRule>,
 }
 
 impl CelValidator {
-    pub fn validate(&self, header: &Header, claims: &JsonValue) -> Result<(), CelValidationError> {
-        // Skip validation if no expression configured (permissive default)
-        let program = match &self.program {
-            Some(p) => p,
-            None => return Ok(()),
-        };
+    /// Adds a list of named rules that are evaluated in order after the main expression
+    pub fn with_rules(mut self, rules: Vec<CelRule>) -> Self {
+        self.rules.extend(rules);
+        self
+    }
 
-        // Build CEL context with header and claims
-        let mut context = Context::default();
+    #[tracing::instrument(
+        name = "cel.validate",
+        skip_all,
+        fields(cel.expression = self.program.is_some(), cel.rules = self.rules.len())
+    )]
+    pub fn validate(
+        &self,
+        header: Option<&Header>,
+        claims: &JsonValue,
+        request: &RequestMetadata,
+    ) -> Result<(), CelValidationError> {
+        // Skip validation if no expression configured (permissive default)
+        if self.program.is_none() && self.rules.is_empty() {
+            return Ok(());
+        }
 
-        // Convert header to CEL-compatible value
-        let header_json = serde_json::to_value(header)
-            .map_err(|e| CelValidationError::ConversionError(e.to_string()))?;
-        let header_value = json_to_cel_value(&header_json);
-        context
-            .add_variable("header", header_value)
-            .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+        let context = build_context(header, claims, request)?;
 
-        // Convert claims to CEL Value
-        let claims_value = json_to_cel_value(claims);
-        context
-            .add_variable("claims", claims_value)
-            .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+        if let Some(program) = &self.program {
+            evaluate_bool(program, &self.expression, &context)?;
+        }
 
-        // Execute the CEL program
-        let result = program
-            .execute(&context)
-            .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+        for rule in &self.rules {
+            if !rule.applies_to(&request.method, &request.path) {
+                continue;
+            }
 
-        // Expect boolean result
-        match result {
-            Value::Bool(true) => Ok(()),
-            Value::Bool(false) => Err(CelValidationError::ExecutionError(format!(
-                "expression '{}' evaluated to false",
-                self.expression
-            ))),
-            _ => Err(CelValidationError::NonBooleanResult),
+            let result = tracing::info_span!("cel.rule", cel.rule = rule.name)
+                .in_scope(|| evaluate_bool(&rule.program, &rule.expression, &context));
+            if let Err(e) = result {
+                match rule.severity {
+                    CelRuleSeverity::Deny => {
+                        return Err(CelValidationError::RuleFailed {
+                            rule: rule.name.clone(),
+                            description: rule.description.clone(),
+                            source: Box::new(e),
+                        })
+                    }
+                    CelRuleSeverity::Log => {
+                        tracing::warn!(
+                            event_type = "cel_rule_failed",
+                            rule = rule.name,
+                            "CEL rule '{}' ({}) failed (log-only): {}",
+                            rule.name,
+                            rule.description,
+                            e
+                        );
+                    }
+                }
+            }
         }
+
+        Ok(())
     }
 
     pub fn expression(&self) -> &str {
         &self.expression
     }
+
+    /// Returns `true` if the outcome depends on the request (and not only on the token)
+    pub fn is_request_dependent(&self) -> bool {
+        self.program
+            .as_ref()
+            .is_some_and(|program| program.references().has_variable("request"))
+            || self.rules.iter().any(CelRule::is_request_dependent)
+    }
 }
 
 impl FromStr for CelValidator {
@@ -74,10 +227,7 @@ impl FromStr for CelValidator {
 
     fn from_str(expression: &str) -> Result<Self, Self::Err> {
         if expression.trim().is_empty() {
-            return Ok(Self {
-                program: None,
-                expression: String::new(),
-            });
+            return Ok(Self::default());
         }
 
         let program = Program::compile(expression)
@@ -86,10 +236,119 @@ impl FromStr for CelValidator {
         Ok(Self {
             program: Some(Arc::new(program)),
             expression: expression.to_string(),
+            rules: vec![],
         })
     }
 }
 
+/// A CEL expression computing a value (rather than a boolean check) from the same
+/// variables available to [`CelValidator`].
+#[derive(Debug, Clone)]
+pub struct CelExpression {
+    program: Arc<Program>,
+    expression: String,
+}
+
+impl CelExpression {
+    pub fn evaluate(
+        &self,
+        header: Option<&Header>,
+        claims: &JsonValue,
+        request: &RequestMetadata,
+    ) -> Result<JsonValue, CelValidationError> {
+        let context = build_context(header, claims, request)?;
+        let result = self
+            .program
+            .execute(&context)
+            .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+
+        result
+            .json()
+            .map_err(|e| CelValidationError::ConversionError(e.to_string()))
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Returns `true` if the result depends on the request (the expression reads `request`)
+    pub fn is_request_dependent(&self) -> bool {
+        self.program.references().has_variable("request")
+    }
+}
+
+impl FromStr for CelExpression {
+    type Err = CelValidationError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let program = Program::compile(expression)
+            .map_err(|e| CelValidationError::CompileError(e.to_string()))?;
+
+        Ok(Self {
+            program: Arc::new(program),
+            expression: expression.to_string(),
+        })
+    }
+}
+
+/// Execute a CEL program expecting a boolean result
+fn evaluate_bool(
+    program: &Program,
+    expression: &str,
+    context: &Context,
+) -> Result<(), CelValidationError> {
+    let result = program
+        .execute(context)
+        .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+
+    // Expect boolean result
+    match result {
+        Value::Bool(true) => Ok(()),
+        Value::Bool(false) => Err(CelValidationError::ExecutionError(format!(
+            "expression '{}' evaluated to false",
+            expression
+        ))),
+        _ => Err(CelValidationError::NonBooleanResult),
+    }
+}
+
+/// Build the CEL context exposing the `header`, `claims` and `request` variables
+/// and the custom functions
+fn build_context<'a>(
+    header: Option<&Header>,
+    claims: &JsonValue,
+    request: &RequestMetadata,
+) -> Result<Context<'a>, CelValidationError> {
+    let mut context = Context::default();
+    cel_functions::register(&mut context);
+
+    // Convert header to CEL-compatible value (an empty map if the token has no header)
+    let header_json = match header {
+        Some(header) => serde_json::to_value(header)
+            .map_err(|e| CelValidationError::ConversionError(e.to_string()))?,
+        None => JsonValue::Object(Default::default()),
+    };
+    let header_value = json_to_cel_value(&header_json);
+    context
+        .add_variable("header", header_value)
+        .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+
+    // Convert claims to CEL Value
+    let claims_value = json_to_cel_value(claims);
+    context
+        .add_variable("claims", claims_value)
+        .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+
+    // Convert request metadata to CEL Value
+    let request_json = serde_json::to_value(request)
+        .map_err(|e| CelValidationError::ConversionError(e.to_string()))?;
+    context
+        .add_variable("request", json_to_cel_value(&request_json))
+        .map_err(|e| CelValidationError::ExecutionError(e.to_string()))?;
+
+    Ok(context)
+}
+
 /// Convert a serde_json::Value to a CEL Value
 fn json_to_cel_value(json: &JsonValue) -> Value {
     match json {
@@ -130,7 +389,9 @@ mod tests {
         let validator: CelValidator = "".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -138,7 +399,9 @@ mod tests {
         let validator: CelValidator = "   ".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -146,7 +409,9 @@ mod tests {
         let validator: CelValidator = r#"claims.sub != """#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -154,7 +419,7 @@ mod tests {
         let validator: CelValidator = r#"claims.sub == """#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        let result = validator.validate(&header, &claims);
+        let result = validator.validate(Some(&header), &claims, &RequestMetadata::default());
         assert!(result.is_err());
         assert!(matches!(result, Err(CelValidationError::ExecutionError(_))));
     }
@@ -162,10 +427,23 @@ mod tests {
     #[test]
     fn it_should_access_header_fields() {
         let validator: CelValidator = r#"header.typ == "JWT""#.parse().unwrap();
-        let mut header = Header::default();
-        header.typ = Some("JWT".to_string());
+        let header = Header {
+            typ: Some("JWT".to_string()),
+            ..Default::default()
+        };
+        let claims = json!({});
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn it_should_expose_an_empty_header_for_tokens_without_header() {
+        let validator: CelValidator = r#"size(header) == 0 && !has(header.alg)"#.parse().unwrap();
         let claims = json!({});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(None, &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -173,7 +451,9 @@ mod tests {
         let validator: CelValidator = r#"header.alg == "HS256""#.parse().unwrap();
         let header = Header::default(); // default is HS256
         let claims = json!({});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -181,7 +461,9 @@ mod tests {
         let validator: CelValidator = "has(claims.email)".parse().unwrap();
         let header = Header::default();
         let claims = json!({"email": "user@example.com"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -189,7 +471,9 @@ mod tests {
         let validator: CelValidator = "!has(claims.email)".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -201,15 +485,21 @@ mod tests {
 
         // Case 1: field is missing - should pass
         let claims = json!({"sub": "user123"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
 
         // Case 2: field is present with correct value - should pass
         let claims = json!({"sub": "user123", "acr": "urn:mfa"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
 
         // Case 3: field is present with wrong value - should fail
         let claims = json!({"sub": "user123", "acr": "wrong"});
-        assert!(validator.validate(&header, &claims).is_err());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_err());
     }
 
     #[test]
@@ -217,7 +507,9 @@ mod tests {
         let validator: CelValidator = r#"claims.email.endsWith("@example.com")"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"email": "user@example.com"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -225,7 +517,9 @@ mod tests {
         let validator: CelValidator = r#"claims.email.startsWith("user")"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"email": "user@example.com"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -233,7 +527,9 @@ mod tests {
         let validator: CelValidator = r#"claims.email.contains("@")"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"email": "user@example.com"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -243,7 +539,9 @@ mod tests {
             .unwrap();
         let header = Header::default();
         let claims = json!({"email": "user@example.com"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -251,7 +549,9 @@ mod tests {
         let validator: CelValidator = r#""admin" in claims.roles"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"roles": ["user", "admin"]});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -259,7 +559,9 @@ mod tests {
         let validator: CelValidator = r#"claims.roles.exists(r, r == "admin")"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"roles": ["user", "admin"]});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -268,7 +570,9 @@ mod tests {
             r#"claims.roles.exists(r, r == "superadmin")"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"roles": ["user", "admin"]});
-        assert!(validator.validate(&header, &claims).is_err());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_err());
     }
 
     #[test]
@@ -277,7 +581,9 @@ mod tests {
             r#"claims.scopes.all(s, s.startsWith("read:"))"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"scopes": ["read:users", "read:posts"]});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -286,7 +592,9 @@ mod tests {
             r#"claims.scopes.all(s, s.startsWith("read:"))"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"scopes": ["read:users", "write:posts"]});
-        assert!(validator.validate(&header, &claims).is_err());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_err());
     }
 
     #[test]
@@ -296,7 +604,9 @@ mod tests {
             .unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123", "email_verified": true});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -306,7 +616,9 @@ mod tests {
             .unwrap();
         let header = Header::default();
         let claims = json!({"role": "superuser"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -316,10 +628,14 @@ mod tests {
         let header = Header::default();
 
         let claims = json!({"count": 10});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
 
         let claims = json!({"count": 3});
-        assert!(validator.validate(&header, &claims).is_err());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_err());
     }
 
     #[test]
@@ -328,7 +644,9 @@ mod tests {
         let validator: CelValidator = r#"claims.aud == "my-client-id""#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"aud": "my-client-id"});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -337,7 +655,9 @@ mod tests {
         let validator: CelValidator = r#""my-client-id" in claims.aud"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"aud": ["other-client", "my-client-id"]});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -352,7 +672,7 @@ mod tests {
         let validator: CelValidator = r#"claims.sub"#.parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        let result = validator.validate(&header, &claims);
+        let result = validator.validate(Some(&header), &claims, &RequestMetadata::default());
         assert!(matches!(result, Err(CelValidationError::NonBooleanResult)));
     }
 
@@ -368,13 +688,72 @@ mod tests {
         assert_eq!(validator.expression(), "");
     }
 
+    #[test]
+    fn it_should_detect_request_dependent_expressions() {
+        let validator: CelValidator = r#"request.method == "GET""#.parse().unwrap();
+        assert!(validator.is_request_dependent());
+        let validator: CelValidator = "claims.email_verified == true".parse().unwrap();
+        assert!(!validator.is_request_dependent());
+        assert!(!CelValidator::default().is_request_dependent());
+
+        let expression: CelExpression = r#"{"path": request.path}"#.parse().unwrap();
+        assert!(expression.is_request_dependent());
+        let expression: CelExpression = "claims.sub".parse().unwrap();
+        assert!(!expression.is_request_dependent());
+    }
+
+    #[test]
+    fn it_should_detect_request_dependent_rules() {
+        let rule = |routes: Vec<String>, expression: &str, severity| {
+            CelRule::new(
+                "rule".to_string(),
+                String::new(),
+                expression,
+                routes,
+                severity,
+            )
+            .unwrap()
+        };
+        let admin_routes = vec!["* /admin/*".to_string()];
+
+        let validator = CelValidator::default().with_rules(vec![rule(
+            vec![],
+            "claims.email_verified == true",
+            CelRuleSeverity::Deny,
+        )]);
+        assert!(!validator.is_request_dependent());
+        for validator in [
+            CelValidator::default().with_rules(vec![rule(
+                admin_routes.clone(),
+                "claims.admin == true",
+                CelRuleSeverity::Deny,
+            )]),
+            CelValidator::default().with_rules(vec![rule(
+                vec![],
+                r#"request.stage == "prod""#,
+                CelRuleSeverity::Deny,
+            )]),
+        ] {
+            assert!(validator.is_request_dependent());
+        }
+        // log-only rules don't change the decision
+        let validator = CelValidator::default().with_rules(vec![rule(
+            admin_routes,
+            "claims.admin == true",
+            CelRuleSeverity::Log,
+        )]);
+        assert!(!validator.is_request_dependent());
+    }
+
     #[test]
     fn it_should_handle_null_values_in_claims() {
         let validator: CelValidator = "!has(claims.optional_field)".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123", "optional_field": null});
         // null fields are present but have null value
-        assert!(validator.validate(&header, &claims).is_err());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_err());
     }
 
     #[test]
@@ -382,7 +761,9 @@ mod tests {
         let validator: CelValidator = "claims.optional_field == null".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123", "optional_field": null});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -390,7 +771,9 @@ mod tests {
         let validator: CelValidator = "claims.score > 0.5".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123", "score": 0.75});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -400,7 +783,9 @@ mod tests {
         let header = Header::default();
         let large_num: u64 = 9223372036854775808; // i64::MAX + 1
         let claims = json!({"sub": "user123", "big_number": large_num});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -408,7 +793,9 @@ mod tests {
         let validator: CelValidator = "claims.offset < 0".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123", "offset": -100});
-        assert!(validator.validate(&header, &claims).is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
     }
 
     #[test]
@@ -417,7 +804,247 @@ mod tests {
         let validator: CelValidator = "claims.missing_field.startsWith(\"x\")".parse().unwrap();
         let header = Header::default();
         let claims = json!({"sub": "user123"});
-        let result = validator.validate(&header, &claims);
+        let result = validator.validate(Some(&header), &claims, &RequestMetadata::default());
+        assert!(matches!(result, Err(CelValidationError::ExecutionError(_))));
+    }
+
+    #[test]
+    fn it_should_access_request_metadata() {
+        let validator: CelValidator =
+            r#"claims.tenant == request.headers["x-tenant"] && request.sourceIp.startsWith("10.")"#
+                .parse()
+                .unwrap();
+        let header = Header::default();
+        let claims = json!({"tenant": "acme"});
+        let request = RequestMetadata {
+            headers: [("x-tenant".to_string(), "acme".to_string())].into(),
+            source_ip: "10.0.0.1".to_string(),
+            ..Default::default()
+        };
+        assert!(validator.validate(Some(&header), &claims, &request).is_ok());
+
+        let request = RequestMetadata {
+            headers: [("x-tenant".to_string(), "other".to_string())].into(),
+            source_ip: "10.0.0.1".to_string(),
+            ..Default::default()
+        };
+        assert!(validator
+            .validate(Some(&header), &claims, &request)
+            .is_err());
+    }
+
+    #[test]
+    fn it_should_bind_empty_request_metadata_for_token_events() {
+        let validator: CelValidator =
+            r#"size(request.headers) == 0 && size(request.stageVariables) == 0 && request.sourceIp == """#
+                .parse()
+                .unwrap();
+        let header = Header::default();
+        let claims = json!({"sub": "user123"});
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
+    }
+
+    fn make_rules_validator(rules: &str) -> CelValidator {
+        CelValidator::default().with_rules(CelRule::load_from_str(rules).unwrap())
+    }
+
+    #[test]
+    fn it_should_load_rules_from_json() {
+        let validator = make_rules_validator(
+            r#"{"rules": [{"name": "has-sub", "description": "sub is required", "expression": "has(claims.sub)"}]}"#,
+        );
+        assert_eq!(validator.rules.len(), 1);
+        assert_eq!(validator.rules[0].name, "has-sub");
+        assert_eq!(validator.rules[0].severity, CelRuleSeverity::Deny);
+        assert_eq!(validator.rules[0].expression, "has(claims.sub)");
+    }
+
+    #[test]
+    fn it_should_fail_to_load_rules_with_invalid_expressions() {
+        let result = CelRule::load_from_str(
+            r#"
+            rules:
+              - name: broken
+                expression: "invalid syntax {{{{"
+            "#,
+        );
+        assert!(matches!(result, Err(CelValidationError::CompileError(_))));
+    }
+
+    #[test]
+    fn it_should_fail_to_load_rules_with_unknown_fields() {
+        let result = CelRule::load_from_str(
+            r#"
+            rules:
+              - name: typo
+                expresion: "true"
+            "#,
+        );
+        assert!(matches!(result, Err(CelValidationError::RulesFileError(_))));
+    }
+
+    #[test]
+    fn it_should_evaluate_rules_in_order_and_name_the_failing_rule() {
+        let validator = make_rules_validator(
+            r#"
+            rules:
+              - name: email-verified
+                description: The email must be verified
+                expression: claims.email_verified == true
+              - name: admin-only
+                description: Only admins are allowed
+                expression: '"admin" in claims.roles'
+            "#,
+        );
+        let header = Header::default();
+        let request = RequestMetadata::default();
+
+        let claims = json!({"email_verified": true, "roles": ["admin"]});
+        assert!(validator.validate(Some(&header), &claims, &request).is_ok());
+
+        let claims = json!({"email_verified": false, "roles": ["user"]});
+        let result = validator.validate(Some(&header), &claims, &request);
+        match result {
+            Err(CelValidationError::RuleFailed {
+                rule, description, ..
+            }) => {
+                assert_eq!(rule, "email-verified");
+                assert_eq!(description, "The email must be verified");
+            }
+            _ => panic!("Expected a RuleFailed error"),
+        }
+
+        let claims = json!({"email_verified": true, "roles": ["user"]});
+        let result = validator.validate(Some(&header), &claims, &request);
+        assert!(
+            matches!(result, Err(CelValidationError::RuleFailed { rule, .. }) if rule == "admin-only")
+        );
+    }
+
+    #[test]
+    fn it_should_evaluate_the_main_expression_before_the_rules() {
+        let validator: CelValidator = "claims.sub != 'blocked'".parse().unwrap();
+        let validator = validator.with_rules(
+            CelRule::load_from_str(
+                r#"{"rules": [{"name": "always-fails", "expression": "false"}]}"#,
+            )
+            .unwrap(),
+        );
+        let header = Header::default();
+        let claims = json!({"sub": "blocked"});
+        let result = validator.validate(Some(&header), &claims, &RequestMetadata::default());
+        assert!(matches!(result, Err(CelValidationError::ExecutionError(_))));
+
+        let claims = json!({"sub": "user123"});
+        let result = validator.validate(Some(&header), &claims, &RequestMetadata::default());
+        assert!(matches!(result, Err(CelValidationError::RuleFailed { .. })));
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn it_should_not_deny_on_log_only_rules() {
+        let validator = make_rules_validator(
+            r#"
+            rules:
+              - name: prefer-mfa
+                expression: claims.amr.exists(m, m == "mfa")
+                severity: log
+            "#,
+        );
+        let header = Header::default();
+        let claims = json!({"amr": ["pwd"]});
+        assert!(validator
+            .validate(Some(&header), &claims, &RequestMetadata::default())
+            .is_ok());
+        assert!(logs_contain("cel_rule_failed"));
+        assert!(logs_contain("prefer-mfa"));
+    }
+
+    #[test]
+    fn it_should_only_evaluate_rules_matching_the_route() {
+        let validator = make_rules_validator(
+            r#"
+            rules:
+              - name: admin-routes
+                expression: '"admin" in claims.roles'
+                routes: ["* /admin/*", "DELETE /users/*"]
+            "#,
+        );
+        let header = Header::default();
+        let claims = json!({"roles": ["user"]});
+        let request = |method: &str, path: &str| RequestMetadata {
+            method: method.to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+
+        assert!(validator
+            .validate(Some(&header), &claims, &request("GET", "/pets"))
+            .is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &request("GET", "/users/42"))
+            .is_ok());
+        assert!(validator
+            .validate(Some(&header), &claims, &request("GET", "/admin/settings"))
+            .is_err());
+        assert!(validator
+            .validate(Some(&header), &claims, &request("DELETE", "/users/42"))
+            .is_err());
+    }
+
+    #[test]
+    fn it_should_support_custom_functions() {
+        let validator: CelValidator =
+            r#"hasScope(claims, "admin") && inCidr(request.sourceIp, "10.0.0.0/8")"#
+                .parse()
+                .unwrap();
+        let header = Header::default();
+        let claims = json!({"scope": "openid admin"});
+        let request = RequestMetadata {
+            source_ip: "10.0.0.1".to_string(),
+            ..Default::default()
+        };
+        assert!(validator.validate(Some(&header), &claims, &request).is_ok());
+
+        let request = RequestMetadata {
+            source_ip: "192.168.1.1".to_string(),
+            ..Default::default()
+        };
+        assert!(validator
+            .validate(Some(&header), &claims, &request)
+            .is_err());
+    }
+
+    #[test]
+    fn it_should_evaluate_expressions_to_values() {
+        let expression: CelExpression =
+            r#"{"user": claims.tenant + ":" + claims.sub, "ip": request.sourceIp}"#
+                .parse()
+                .unwrap();
+        let header = Header::default();
+        let claims = json!({"sub": "user123", "tenant": "acme"});
+        let request = RequestMetadata {
+            source_ip: "10.0.0.1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            expression
+                .evaluate(Some(&header), &claims, &request)
+                .unwrap(),
+            json!({"user": "acme:user123", "ip": "10.0.0.1"})
+        );
+    }
+
+    #[test]
+    fn it_should_fail_to_evaluate_expressions_with_errors() {
+        let expression: CelExpression = "claims.missing + 1".parse().unwrap();
+        let result = expression.evaluate(
+            Some(&Header::default()),
+            &json!({}),
+            &RequestMetadata::default(),
+        );
         assert!(matches!(result, Err(CelValidationError::ExecutionError(_))));
     }
 }
@@ -0,0 +1,157 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CertificateBindingError {
+    #[error("Invalid certificate bound tokens mode '{0}'. Supported values: disabled, allowed, required")]
+    InvalidMode(String),
+    #[error("The request has no client certificate (mutual TLS must be enabled on the API)")]
+    MissingCertificate,
+    #[error("Invalid client certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("The token is not bound to a client certificate (missing cnf.x5t#S256 claim)")]
+    UnboundToken,
+    #[error("The client certificate does not match the certificate the token is bound to")]
+    Mismatch,
+}
+
+/// Whether certificate-bound tokens (RFC 8705) are validated
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CertificateBindingMode {
+    /// The `cnf` claim is ignored
+    #[default]
+    Disabled,
+    /// Tokens bound to a certificate must be presented with that certificate
+    Allowed,
+    /// Only tokens bound to the presented certificate are accepted
+    Required,
+}
+
+impl FromStr for CertificateBindingMode {
+    type Err = CertificateBindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "disabled" => Ok(CertificateBindingMode::Disabled),
+            "allowed" => Ok(CertificateBindingMode::Allowed),
+            "required" => Ok(CertificateBindingMode::Required),
+            _ => Err(CertificateBindingError::InvalidMode(s.to_string())),
+        }
+    }
+}
+
+/// Computes the SHA-256 thumbprint (`x5t#S256`) of a PEM encoded certificate
+pub fn certificate_thumbprint(pem: &str) -> Result<String, CertificateBindingError> {
+    let base64_der: String = pem
+        .trim()
+        .strip_prefix("-----BEGIN CERTIFICATE-----")
+        .and_then(|pem| pem.trim_end().strip_suffix("-----END CERTIFICATE-----"))
+        .ok_or(CertificateBindingError::InvalidCertificate(
+            "expected a PEM encoded certificate".to_string(),
+        ))?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let der = STANDARD
+        .decode(base64_der)
+        .map_err(|e| CertificateBindingError::InvalidCertificate(e.to_string()))?;
+
+    Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(der)))
+}
+
+/// Validates that certificate-bound access tokens (RFC 8705) are presented with the
+/// client certificate they have been issued for (API Gateway mutual TLS)
+#[derive(Debug, Clone, Default)]
+pub struct CertificateBinding {
+    mode: CertificateBindingMode,
+}
+
+impl CertificateBinding {
+    pub fn new(mode: CertificateBindingMode) -> Self {
+        Self { mode }
+    }
+
+    /// Returns `true` if certificate-bound tokens are validated. The result depends on the
+    /// client certificate of the request, not only on the token.
+    pub fn is_enabled(&self) -> bool {
+        self.mode != CertificateBindingMode::Disabled
+    }
+
+    pub fn validate(
+        &self,
+        claims: &Value,
+        client_cert_pem: Option<&str>,
+    ) -> Result<(), CertificateBindingError> {
+        if self.mode == CertificateBindingMode::Disabled {
+            return Ok(());
+        }
+
+        let Some(x5t) = claims["cnf"]["x5t#S256"].as_str() else {
+            return match self.mode {
+                CertificateBindingMode::Required => Err(CertificateBindingError::UnboundToken),
+                _ => Ok(()),
+            };
+        };
+        let client_cert_pem = client_cert_pem.ok_or(CertificateBindingError::MissingCertificate)?;
+        if certificate_thumbprint(client_cert_pem)? != x5t {
+            return Err(CertificateBindingError::Mismatch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // computed with `openssl x509 -in client.pem -outform DER | openssl dgst -sha256 -binary | base64`
+    const CLIENT_CERT_THUMBPRINT: &str = "UWxUAmMgMg1FLOruk3Y6YcIe9RZl-LnrajpLcNcVlZU";
+
+    #[test]
+    fn it_should_compute_the_certificate_thumbprint() {
+        let pem = include_str!("../tests/fixtures/certs/client.pem");
+        assert_eq!(certificate_thumbprint(pem).unwrap(), CLIENT_CERT_THUMBPRINT);
+        // API Gateway might provide the certificate without line breaks
+        assert_eq!(
+            certificate_thumbprint(&pem.replace('\n', "")).unwrap(),
+            CLIENT_CERT_THUMBPRINT
+        );
+        assert!(certificate_thumbprint("not a certificate").is_err());
+    }
+
+    #[test]
+    fn it_should_validate_certificate_bound_tokens() {
+        let pem = include_str!("../tests/fixtures/certs/client.pem");
+        let bound_claims = json!({ "cnf": { "x5t#S256": CLIENT_CERT_THUMBPRINT } });
+        let other_claims = json!({ "cnf": { "x5t#S256": "another-thumbprint" } });
+        let unbound_claims = json!({ "sub": "some_user" });
+
+        let binding = CertificateBinding::default();
+        assert!(binding.validate(&other_claims, None).is_ok());
+
+        let binding = CertificateBinding::new(CertificateBindingMode::Allowed);
+        assert!(binding.validate(&bound_claims, Some(pem)).is_ok());
+        assert!(binding.validate(&unbound_claims, None).is_ok());
+        assert!(matches!(
+            binding.validate(&other_claims, Some(pem)),
+            Err(CertificateBindingError::Mismatch)
+        ));
+        assert!(matches!(
+            binding.validate(&bound_claims, None),
+            Err(CertificateBindingError::MissingCertificate)
+        ));
+
+        let binding = CertificateBinding::new(CertificateBindingMode::Required);
+        assert!(matches!(
+            binding.validate(&unbound_claims, Some(pem)),
+            Err(CertificateBindingError::UnboundToken)
+        ));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::cel_validation::{CelRule, CelRuleSeverity, CelValidationError};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClaimConstraintError {
+    #[error("Invalid claim constraint '{0}'. Supported constraints: 'claim exists', 'claim=value', 'claim!=value', 'claim contains value', 'claim in [value1, value2]'")]
+    Malformed(String),
+    #[error(transparent)]
+    Compile(#[from] CelValidationError),
+}
+
+/// Converts a (dotted) claim path to a CEL expression accessing the claim
+fn claim_expression(segments: &[String]) -> String {
+    segments
+        .iter()
+        .fold("claims".to_string(), |expression, segment| {
+            format!("{}[{}]", expression, string_literal(segment))
+        })
+}
+
+fn string_literal(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Converts a constraint value to a CEL literal: booleans and numbers are kept as they are,
+/// anything else (optionally quoted) is a string
+fn literal(value: &str) -> String {
+    let value = value.trim();
+    let is_number = value.parse::<i64>().is_ok()
+        || (value
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == '-')
+            && value.parse::<f64>().is_ok());
+    match value {
+        "true" | "false" => value.to_string(),
+        _ if is_number => value.to_string(),
+        _ => {
+            let unquoted = [('"', '"'), ('\'', '\'')]
+                .iter()
+                .find_map(|(start, end)| value.strip_prefix(*start)?.strip_suffix(*end))
+                .unwrap_or(value);
+            string_literal(unquoted)
+        }
+    }
+}
+
+/// Compiles a declarative constraint into a named CEL rule (e.g. `email_verified=true`
+/// becomes `claims["email_verified"] == true`)
+fn compile(constraint: &str) -> Result<CelRule, ClaimConstraintError> {
+    let malformed = || ClaimConstraintError::Malformed(constraint.to_string());
+    let parse_path = |path: &str| -> Result<Vec<String>, ClaimConstraintError> {
+        let segments: Vec<String> = path.trim().split('.').map(str::to_string).collect();
+        match segments.iter().any(|s| s.is_empty() || s.contains(' ')) {
+            true => Err(malformed()),
+            false => Ok(segments),
+        }
+    };
+
+    // the path can't contain spaces or operators, so it ends where the operator starts
+    // (values can then contain anything, e.g. `city=Born in Rome`)
+    let constraint_start = constraint.trim_start();
+    let path_end = constraint_start
+        .find([' ', '=', '!'])
+        .ok_or_else(malformed)?;
+    let (path, operation) = constraint_start.split_at(path_end);
+    let operation = operation.trim_start();
+
+    let (expression, description) = if operation == "exists" {
+        let segments = parse_path(path)?;
+        let expression = (0..segments.len())
+            .map(|i| {
+                format!(
+                    "{} in {}",
+                    string_literal(&segments[i]),
+                    claim_expression(&segments[..i])
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" && ");
+        (expression, "is required".to_string())
+    } else if let Some(value) = operation.strip_prefix("!=") {
+        let segments = parse_path(path)?;
+        (
+            format!("{} != {}", claim_expression(&segments), literal(value)),
+            format!("must not be {}", value.trim()),
+        )
+    } else if let Some(value) = operation.strip_prefix('=') {
+        let segments = parse_path(path)?;
+        (
+            format!("{} == {}", claim_expression(&segments), literal(value)),
+            format!("must be {}", value.trim()),
+        )
+    } else if let Some(value) = operation.strip_prefix("contains ") {
+        let segments = parse_path(path)?;
+        (
+            format!("{} in {}", literal(value), claim_expression(&segments)),
+            format!("must contain {}", value.trim()),
+        )
+    } else if let Some(values) = operation.strip_prefix("in ") {
+        let segments = parse_path(path)?;
+        let values = values
+            .trim()
+            .strip_prefix('[')
+            .and_then(|values| values.strip_suffix(']'))
+            .ok_or_else(malformed)?;
+        let values: Vec<String> = values
+            .split(',')
+            .filter(|v| !v.trim().is_empty())
+            .map(literal)
+            .collect();
+        (
+            format!("{} in [{}]", claim_expression(&segments), values.join(", ")),
+            format!("must be one of [{}]", values.join(", ")),
+        )
+    } else {
+        return Err(malformed());
+    };
+
+    Ok(CelRule::new(
+        constraint.to_string(),
+        format!("the claim '{}' {}", path.trim(), description),
+        &expression,
+        vec![],
+        CelRuleSeverity::Deny,
+    )?)
+}
+
+/// A list of declarative claim constraints, separated by `;` or new lines
+/// (e.g. `email_verified=true; groups contains admins; tenant in [a, b]; sub exists`).
+/// Every constraint is compiled into a CEL rule evaluated by the `CelValidator`.
+#[derive(Debug, Clone, Default)]
+pub struct ClaimConstraints(Vec<CelRule>);
+
+impl ClaimConstraints {
+    pub fn into_rules(self) -> Vec<CelRule> {
+        self.0
+    }
+}
+
+impl FromStr for ClaimConstraints {
+    type Err = ClaimConstraintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s
+            .split([';', '\n'])
+            .map(str::trim)
+            .filter(|constraint| !constraint.is_empty())
+            .map(compile)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self(rules))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cel_validation::CelValidator, models::RequestMetadata};
+    use jsonwebtoken::Header;
+    use serde_json::{json, Value};
+
+    fn validate(constraints: &str, claims: Value) -> Result<(), CelValidationError> {
+        let constraints: ClaimConstraints = constraints.parse().unwrap();
+        CelValidator::default()
+            .with_rules(constraints.into_rules())
+            .validate(
+                Some(&Header::default()),
+                &claims,
+                &RequestMetadata::default(),
+            )
+    }
+
+    #[test]
+    fn it_should_compile_constraints() {
+        let constraints: ClaimConstraints =
+            "email_verified=true; groups contains admins\n tenant in [a, 'b']; ext.user.id exists"
+                .parse()
+                .unwrap();
+        let rules = constraints.into_rules();
+        assert_eq!(
+            rules.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+            vec![
+                "email_verified=true",
+                "groups contains admins",
+                "tenant in [a, 'b']",
+                "ext.user.id exists"
+            ]
+        );
+        assert_eq!(
+            rules[2].description,
+            "the claim 'tenant' must be one of [\"a\", \"b\"]"
+        );
+    }
+
+    #[test]
+    fn it_should_parse_the_path_before_the_operator() {
+        let claims = json!({
+            "city": "Born in Rome",
+            "motto": "contains exists",
+            "note": "a=b",
+            "tags": ["x in y"],
+        });
+        assert!(validate("city=Born in Rome", claims.clone()).is_ok());
+        assert!(validate("city != Born in Milan", claims.clone()).is_ok());
+        assert!(validate("motto=contains exists", claims.clone()).is_ok());
+        assert!(validate("note=a=b", claims.clone()).is_ok());
+        assert!(validate("tags contains x in y", claims.clone()).is_ok());
+        assert!(validate("city in [Born in Rome, Milan]", claims.clone()).is_ok());
+        assert!(validate("city=Born in Milan", claims.clone()).is_err());
+
+        let constraints: ClaimConstraints = "city=Born in Rome".parse().unwrap();
+        assert_eq!(
+            constraints.into_rules()[0].description,
+            "the claim 'city' must be Born in Rome"
+        );
+    }
+
+    #[test]
+    fn it_should_fail_to_parse_invalid_constraints() {
+        for constraint in [
+            "email_verified",
+            "tenant in a, b",
+            "=true",
+            "a..b exists",
+            "sub exists now",
+            "tenant is acme",
+        ] {
+            assert!(matches!(
+                constraint.parse::<ClaimConstraints>(),
+                Err(ClaimConstraintError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn it_should_validate_claims() {
+        let claims = json!({
+            "sub": "user123",
+            "email_verified": true,
+            "level": 3,
+            "groups": ["users", "admins"],
+            "tenant": "acme",
+            "ext": { "user": { "id": "u1" } },
+        });
+        assert!(validate("sub exists", claims.clone()).is_ok());
+        assert!(validate("ext.user.id exists", claims.clone()).is_ok());
+        assert!(validate("email_verified=true", claims.clone()).is_ok());
+        assert!(validate("level = 3", claims.clone()).is_ok());
+        assert!(validate("tenant != \"globex\"", claims.clone()).is_ok());
+        assert!(validate("groups contains admins", claims.clone()).is_ok());
+        assert!(validate("tenant in [acme, globex]", claims.clone()).is_ok());
+        assert!(validate(
+            "sub exists; email_verified=true; groups contains users",
+            claims.clone()
+        )
+        .is_ok());
+
+        for constraint in [
+            "email exists",
+            "ext.user.email exists",
+            "email_verified=false",
+            "tenant=globex",
+            "groups contains superusers",
+            "tenant in [globex, initech]",
+            "missing=1",
+        ] {
+            let result = validate(constraint, claims.clone());
+            assert!(
+                matches!(&result, Err(CelValidationError::RuleFailed { rule, .. }) if rule == constraint),
+                "constraint '{}' should fail: {:?}",
+                constraint,
+                result
+            );
+        }
+    }
+}
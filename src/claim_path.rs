@@ -0,0 +1,94 @@
+use serde_json::Value;
+
+/// Finds a claim in the token payload using its name, a dotted path (e.g. `ext.user.email`
+/// or `groups.0` for array items) or a JSON Pointer (e.g. `/ext/user/email`).
+/// Claims whose name contains dots (e.g. URLs) take precedence over dotted paths.
+pub fn lookup<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(value) = claims.get(path) {
+        return Some(value);
+    }
+    if path.starts_with('/') {
+        return claims.pointer(path);
+    }
+    path.split('.')
+        .try_fold(claims, |value, segment| match value {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            value => value.get(segment),
+        })
+}
+
+/// Converts a claim to a string (non-string values are converted to their JSON representation)
+pub fn to_string(claim: &Value) -> String {
+    match claim {
+        Value::String(s) => s.clone(),
+        claim => claim.to_string(),
+    }
+}
+
+/// Renders a template where every `{path}` placeholder is replaced with the given claim
+/// (e.g. `{iss}|{sub}`). Returns `None` if any of the claims is missing.
+pub fn render(template: &str, claims: &Value) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        result.push_str(&rest[..start]);
+        result.push_str(&to_string(lookup(claims, &rest[start + 1..end])?));
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_should_lookup_claims() {
+        let claims = json!({
+            "sub": "user123",
+            "ext": { "user": { "email": "user@example.com" } },
+            "groups": ["a", "b"],
+            "https://example.com/tenant": "acme",
+        });
+        assert_eq!(lookup(&claims, "sub"), Some(&json!("user123")));
+        assert_eq!(
+            lookup(&claims, "ext.user.email"),
+            Some(&json!("user@example.com"))
+        );
+        assert_eq!(
+            lookup(&claims, "/ext/user/email"),
+            Some(&json!("user@example.com"))
+        );
+        assert_eq!(lookup(&claims, "groups.1"), Some(&json!("b")));
+        assert_eq!(lookup(&claims, "/groups/0"), Some(&json!("a")));
+        assert_eq!(
+            lookup(&claims, "https://example.com/tenant"),
+            Some(&json!("acme"))
+        );
+        assert_eq!(lookup(&claims, "groups.2"), None);
+        assert_eq!(lookup(&claims, "groups.first"), None);
+        assert_eq!(lookup(&claims, "ext.user.name"), None);
+    }
+
+    #[test]
+    fn it_should_render_templates() {
+        let claims = json!({
+            "iss": "https://idp.example.com",
+            "sub": "user123",
+            "ext": { "tenant": "acme", "level": 3 },
+        });
+        assert_eq!(
+            render("{iss}|{sub}", &claims),
+            Some("https://idp.example.com|user123".to_string())
+        );
+        assert_eq!(
+            render("tenant:{ext.tenant}:{/ext/level}", &claims),
+            Some("tenant:acme:3".to_string())
+        );
+        assert_eq!(render("{iss}|{email}", &claims), None);
+        assert_eq!(render("{iss", &claims), None);
+    }
+}
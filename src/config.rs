@@ -0,0 +1,496 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env::{self, VarError},
+    path::Path,
+};
+use thiserror::Error;
+
+/// The latest version of the config file schema
+pub static CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}")]
+    Unreadable(String),
+    #[error("Invalid config file: {0}")]
+    Invalid(String),
+    #[error(
+        "Unsupported config file version {0} (the latest supported version is {CONFIG_VERSION})"
+    )]
+    UnsupportedVersion(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JwksSection {
+    uri: Option<String>,
+    min_refresh_rate: Option<u64>,
+    pre_cached_file_path: Option<String>,
+}
+
+/// An accepted issuer, optionally with the claim carrying its client ID
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IssuerEntry {
+    Issuer(String),
+    WithSettings(IssuerSettings),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IssuerSettings {
+    issuer: String,
+    client_id_claim: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientIdsSection {
+    accepted: Option<Vec<String>>,
+    claims: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CelSection {
+    expression: Option<String>,
+    claim_constraints: Option<Vec<String>>,
+    rules_file: Option<String>,
+    self_test_file: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrincipalIdSection {
+    claims: Option<Vec<String>>,
+    cel: Option<String>,
+    default: Option<String>,
+}
+
+/// A claim added to the context, either as `path->key[:type]` or as a table
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ContextClaimEntry {
+    Mapping(String),
+    WithSettings(ContextClaimSettings),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextClaimSettings {
+    claim: String,
+    key: Option<String>,
+    #[serde(rename = "type")]
+    value_type: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextSection {
+    claims: Option<Vec<ContextClaimEntry>>,
+    cel: Option<String>,
+    array_separator: Option<String>,
+    jwt_claims: Option<bool>,
+    tenant_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RbacSection {
+    role_claims: Option<Vec<String>>,
+    routes_file: Option<String>,
+    client_id: Option<String>,
+}
+
+/// The schema of the config file. Every value maps to the environment variable
+/// with the same meaning (e.g. `jwks.uri` to `JWKS_URI`).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    version: u32,
+    strict_startup: Option<bool>,
+    #[serde(default)]
+    jwks: JwksSection,
+    issuers: Option<Vec<IssuerEntry>>,
+    audiences: Option<Vec<String>>,
+    #[serde(default)]
+    client_ids: ClientIdsSection,
+    algorithms: Option<Vec<String>>,
+    #[serde(default)]
+    cel: CelSection,
+    #[serde(default)]
+    principal_id: PrincipalIdSection,
+    #[serde(default)]
+    context: ContextSection,
+    #[serde(default)]
+    rbac: RbacSection,
+}
+
+impl ConfigFile {
+    fn into_values(self) -> HashMap<&'static str, String> {
+        let mut values = HashMap::new();
+        let mut set = |name: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                values.insert(name, value);
+            }
+        };
+        let join = |values: Option<Vec<String>>, separator: &str| {
+            values.map(|values| values.join(separator))
+        };
+
+        set(
+            "STRICT_STARTUP",
+            self.strict_startup.map(|enabled| enabled.to_string()),
+        );
+        set("JWKS_URI", self.jwks.uri);
+        set(
+            "MIN_REFRESH_RATE",
+            self.jwks.min_refresh_rate.map(|rate| rate.to_string()),
+        );
+        set("JWKS_PRE_CACHED_FILE_PATH", self.jwks.pre_cached_file_path);
+
+        if let Some(issuers) = self.issuers {
+            let mut accepted_issuers = Vec::with_capacity(issuers.len());
+            let mut issuer_claims = Vec::new();
+            for issuer in issuers {
+                match issuer {
+                    IssuerEntry::Issuer(issuer) => accepted_issuers.push(issuer),
+                    IssuerEntry::WithSettings(settings) => {
+                        if let Some(claim) = settings.client_id_claim {
+                            issuer_claims.push(format!("{}={}", settings.issuer, claim));
+                        }
+                        accepted_issuers.push(settings.issuer);
+                    }
+                }
+            }
+            set("ACCEPTED_ISSUERS", Some(accepted_issuers.join(", ")));
+            set("CLIENT_ID_ISSUER_CLAIMS", Some(issuer_claims.join(", ")));
+        }
+        set("ACCEPTED_AUDIENCES", join(self.audiences, ", "));
+        set("ACCEPTED_CLIENT_IDS", join(self.client_ids.accepted, ", "));
+        set("CLIENT_ID_CLAIMS", join(self.client_ids.claims, ", "));
+        set("ACCEPTED_ALGORITHMS", join(self.algorithms, ", "));
+
+        set("TOKEN_VALIDATION_CEL", self.cel.expression);
+        set("CLAIM_CONSTRAINTS", join(self.cel.claim_constraints, "; "));
+        set("TOKEN_VALIDATION_CEL_RULES_FILE", self.cel.rules_file);
+        set("CEL_SELF_TEST_FILE", self.cel.self_test_file);
+
+        set("PRINCIPAL_ID_CLAIMS", join(self.principal_id.claims, ", "));
+        set("PRINCIPAL_ID_CEL", self.principal_id.cel);
+        set("DEFAULT_PRINCIPAL_ID", self.principal_id.default);
+
+        set(
+            "CONTEXT_CLAIMS",
+            self.context.claims.map(|claims| {
+                claims
+                    .into_iter()
+                    .map(|claim| match claim {
+                        ContextClaimEntry::Mapping(mapping) => mapping,
+                        ContextClaimEntry::WithSettings(settings) => format!(
+                            "{}->{}:{}",
+                            settings.claim,
+                            settings.key.unwrap_or_default(),
+                            settings.value_type.unwrap_or_default()
+                        ),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }),
+        );
+        set("CONTEXT_CEL", self.context.cel);
+        set("CONTEXT_ARRAY_SEPARATOR", self.context.array_separator);
+        set(
+            "CONTEXT_JWT_CLAIMS",
+            self.context.jwt_claims.map(|enabled| enabled.to_string()),
+        );
+        set("CONTEXT_TENANT_KEY", self.context.tenant_key);
+
+        set("ROLE_CLAIMS", join(self.rbac.role_claims, ", "));
+        set("RBAC_ROUTES_FILE", self.rbac.routes_file);
+        set("RBAC_CLIENT_ID", self.rbac.client_id);
+
+        values
+    }
+}
+
+/// The configuration of the authorizer. Values are read from the environment variables
+/// first and then from the optional config file (`CONFIG_FILE`).
+#[derive(Debug, Clone)]
+pub struct Config {
+    file_values: HashMap<&'static str, String>,
+    env_var: fn(&str) -> Result<String, VarError>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file_values: HashMap::new(),
+            env_var: |name| env::var(name),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file set in `CONFIG_FILE` (if any)
+    pub fn load() -> Result<Self, ConfigError> {
+        match env::var("CONFIG_FILE") {
+            Ok(path) if !path.trim().is_empty() => Self::load_from_file(path.trim()),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Loads a YAML, TOML or JSON config file (the format depends on the file extension)
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Unreadable(format!("'{}': {}", path.display(), e)))?;
+        Self::load_from_str(&content, ConfigFormat::from_path(path))
+    }
+
+    fn load_from_str(content: &str, format: ConfigFormat) -> Result<Self, ConfigError> {
+        let invalid = |e: &dyn std::fmt::Display| ConfigError::Invalid(e.to_string());
+        let config_file: ConfigFile = match format {
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| invalid(&e)),
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| invalid(&e)),
+            ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| invalid(&e)),
+        }?;
+        if config_file.version != CONFIG_VERSION {
+            return Err(ConfigError::UnsupportedVersion(config_file.version));
+        }
+
+        Ok(Self {
+            file_values: config_file.into_values(),
+            ..Self::default()
+        })
+    }
+
+    /// Replaces the lookup of the environment variables (used by the tests)
+    #[cfg(test)]
+    fn with_env_var(mut self, env_var: fn(&str) -> Result<String, VarError>) -> Self {
+        self.env_var = env_var;
+        self
+    }
+
+    /// Reads a setting: a (non empty) environment variable takes precedence over the config file
+    pub fn var(&self, name: &str) -> Result<String, VarError> {
+        let value = (self.env_var)(name);
+        match (&value, self.file_values.get(name)) {
+            (Ok(env_value), Some(file_value)) if env_value.is_empty() => Ok(file_value.clone()),
+            (Err(VarError::NotPresent), Some(file_value)) => Ok(file_value.clone()),
+            _ => value,
+        }
+    }
+
+    /// Reads a setting, falling back to `default` when it is neither set nor in the config file
+    pub fn var_or(&self, name: &str, default: &str) -> String {
+        self.var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| default.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_load_a_yaml_config_file() {
+        let config = Config::load_from_str(
+            r#"
+version: 1
+strict_startup: true
+jwks:
+  uri: https://idp.example.com/.well-known/jwks.json
+  min_refresh_rate: 600
+issuers:
+  - https://idp.example.com
+  - issuer: "https://login.microsoftonline.com/{tenant}/v2.0"
+    client_id_claim: appid
+audiences: [api-1, api-2]
+algorithms: [RS256, ES256]
+cel:
+  claim_constraints: [email_verified=true, sub exists]
+principal_id:
+  claims: [email, sub]
+context:
+  claims:
+    - sub->userId
+    - { claim: age, type: number }
+  jwt_claims: false
+  tenant_key: tenant
+"#,
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+
+        assert_eq!(config.file_values.get("STRICT_STARTUP").unwrap(), "true");
+        assert_eq!(config.file_values.get("MIN_REFRESH_RATE").unwrap(), "600");
+        assert_eq!(
+            config.file_values.get("ACCEPTED_ISSUERS").unwrap(),
+            "https://idp.example.com, https://login.microsoftonline.com/{tenant}/v2.0"
+        );
+        assert_eq!(
+            config.file_values.get("CLIENT_ID_ISSUER_CLAIMS").unwrap(),
+            "https://login.microsoftonline.com/{tenant}/v2.0=appid"
+        );
+        assert_eq!(
+            config.file_values.get("ACCEPTED_AUDIENCES").unwrap(),
+            "api-1, api-2"
+        );
+        assert_eq!(
+            config.file_values.get("ACCEPTED_ALGORITHMS").unwrap(),
+            "RS256, ES256"
+        );
+        assert_eq!(
+            config.file_values.get("CLAIM_CONSTRAINTS").unwrap(),
+            "email_verified=true; sub exists"
+        );
+        assert_eq!(
+            config.file_values.get("PRINCIPAL_ID_CLAIMS").unwrap(),
+            "email, sub"
+        );
+        assert_eq!(
+            config.file_values.get("CONTEXT_CLAIMS").unwrap(),
+            "sub->userId, age->:number"
+        );
+        assert_eq!(
+            config.file_values.get("CONTEXT_JWT_CLAIMS").unwrap(),
+            "false"
+        );
+        assert_eq!(
+            config.file_values.get("CONTEXT_TENANT_KEY").unwrap(),
+            "tenant"
+        );
+        assert!(!config.file_values.contains_key("TOKEN_VALIDATION_CEL"));
+    }
+
+    #[test]
+    fn it_should_load_toml_and_json_config_files() {
+        let config = Config::load_from_str(
+            r#"
+version = 1
+audiences = ["api-1"]
+
+[principal_id]
+default = "anonymous"
+"#,
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(
+            config.file_values.get("ACCEPTED_AUDIENCES").unwrap(),
+            "api-1"
+        );
+        assert_eq!(
+            config.file_values.get("DEFAULT_PRINCIPAL_ID").unwrap(),
+            "anonymous"
+        );
+
+        let config = Config::load_from_str(
+            r#"{"version": 1, "cel": {"expression": "claims.email_verified == true"}}"#,
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        assert_eq!(
+            config.file_values.get("TOKEN_VALIDATION_CEL").unwrap(),
+            "claims.email_verified == true"
+        );
+    }
+
+    #[test]
+    fn it_should_reject_invalid_config_files() {
+        for (content, format) in [
+            ("version: 1\naudience: [api-1]", ConfigFormat::Yaml),
+            (
+                "version: 1\njwks:\n  url: https://idp.example.com",
+                ConfigFormat::Yaml,
+            ),
+            ("audiences: [api-1]", ConfigFormat::Yaml),
+            (
+                "version = 1\n[context]\nseparator = \";\"",
+                ConfigFormat::Toml,
+            ),
+            (
+                r#"{"version": 1, "algorithm": ["RS256"]}"#,
+                ConfigFormat::Json,
+            ),
+        ] {
+            assert!(
+                matches!(
+                    Config::load_from_str(content, format),
+                    Err(ConfigError::Invalid(_))
+                ),
+                "'{}' should be rejected",
+                content
+            );
+        }
+        assert!(matches!(
+            Config::load_from_str("version: 2", ConfigFormat::Yaml),
+            Err(ConfigError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn it_should_give_precedence_to_environment_variables() {
+        let config = Config::load_from_str(
+            "version: 1\nrbac:\n  routes_file: /opt/routes.yml\n  role_claims: [groups]",
+            ConfigFormat::Yaml,
+        )
+        .unwrap()
+        .with_env_var(|name| match name {
+            "ROLE_CLAIMS" => Ok("roles".to_string()),
+            "RBAC_ROUTES_FILE" => Ok(String::new()),
+            _ => Err(VarError::NotPresent),
+        });
+
+        assert_eq!(config.var("ROLE_CLAIMS").unwrap(), "roles");
+        // empty environment variables are ignored
+        assert_eq!(config.var("RBAC_ROUTES_FILE").unwrap(), "/opt/routes.yml");
+        assert_eq!(config.var("CONTEXT_CEL"), Err(VarError::NotPresent));
+    }
+
+    #[test]
+    fn it_should_read_the_config_file_when_environment_variables_are_absent_or_empty() {
+        let config = Config::load_from_str(
+            "version: 1\nstrict_startup: true\njwks:\n  min_refresh_rate: 600\ncontext:\n  array_separator: '|'",
+            ConfigFormat::Yaml,
+        )
+        .unwrap()
+        // the template sets every parameter, with an empty default
+        .with_env_var(|name| match name {
+            "MIN_REFRESH_RATE" | "CONTEXT_ARRAY_SEPARATOR" | "PRINCIPAL_ID_CLAIMS" => {
+                Ok(String::new())
+            }
+            _ => Err(VarError::NotPresent),
+        });
+
+        assert_eq!(config.var("STRICT_STARTUP").unwrap(), "true");
+        assert_eq!(config.var_or("MIN_REFRESH_RATE", "900"), "600");
+        assert_eq!(config.var_or("CONTEXT_ARRAY_SEPARATOR", ","), "|");
+        assert_eq!(
+            config.var_or("PRINCIPAL_ID_CLAIMS", "preferred_username, sub"),
+            "preferred_username, sub"
+        );
+        assert_eq!(config.var_or("RESPONSE_SIZE_LIMIT", "8192"), "8192");
+    }
+}
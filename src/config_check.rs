@@ -0,0 +1,548 @@
+use crate::{
+    accepted_claims::TENANT_PLACEHOLDER, config::Config, denylist::DenylistEntries,
+    glob_pattern::glob_match, keysmap::KeysMap,
+};
+use jsonwebtoken::jwk::JwkSet;
+use reqwest::{Client, Url};
+use std::{collections::HashSet, fmt::Display, path::PathBuf, time::Duration};
+
+/// How long to wait for the JWKS endpoint when checking the configuration. The check can run
+/// during the Lambda init phase (`STRICT_STARTUP`), which is limited to 10 seconds.
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IssueSeverity {
+    Error,
+    Warning,
+}
+
+/// A problem found in the configuration. `setting` is the related environment variable
+/// (if the problem can be attributed to a specific setting).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConfigIssue {
+    pub severity: IssueSeverity,
+    pub setting: Option<&'static str>,
+    pub message: String,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            IssueSeverity::Error => "ERROR",
+            IssueSeverity::Warning => "WARNING",
+        };
+        match self.setting {
+            Some(setting) => write!(f, "{} {}: {}", severity, setting, self.message),
+            None => write!(f, "{} {}", severity, self.message),
+        }
+    }
+}
+
+/// The errors and warnings found while validating the configuration
+#[derive(Debug, Clone, Default)]
+pub struct ConfigReport {
+    issues: Vec<ConfigIssue>,
+}
+
+impl ConfigReport {
+    pub fn error(&mut self, setting: Option<&'static str>, message: impl Display) {
+        self.issues.push(ConfigIssue {
+            severity: IssueSeverity::Error,
+            setting,
+            message: message.to_string(),
+        });
+    }
+
+    pub fn warning(&mut self, setting: Option<&'static str>, message: impl Display) {
+        self.issues.push(ConfigIssue {
+            severity: IssueSeverity::Warning,
+            setting,
+            message: message.to_string(),
+        });
+    }
+
+    /// Returns the value of a setting or, if it is invalid, records the error and returns
+    /// the default value, so that all the invalid settings can be reported at once
+    pub fn check<T: Default>(
+        &mut self,
+        setting: &'static str,
+        result: Result<T, impl Display>,
+    ) -> T {
+        result.unwrap_or_else(|e| {
+            self.error(Some(setting), e);
+            T::default()
+        })
+    }
+
+    pub fn extend(&mut self, other: ConfigReport) {
+        self.issues.extend(other.issues);
+    }
+
+    pub fn issues(&self) -> &[ConfigIssue] {
+        &self.issues
+    }
+
+    fn count(&self, severity: IssueSeverity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.count(IssueSeverity::Error) > 0
+    }
+}
+
+impl Display for ConfigReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        write!(
+            f,
+            "{} error(s), {} warning(s)",
+            self.count(IssueSeverity::Error),
+            self.count(IssueSeverity::Warning)
+        )
+    }
+}
+
+impl std::error::Error for ConfigReport {}
+
+fn split_comma_separated_values(comma_separated_values: &str) -> Vec<String> {
+    comma_separated_values
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Returns the host of an issuer, keeping wildcards and placeholders
+/// (e.g. `*.eu.auth0.com` for `https://*.eu.auth0.com/`)
+fn issuer_host(issuer: &str) -> &str {
+    let without_scheme = issuer
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(issuer);
+    without_scheme
+        .split(['/', ':'])
+        .next()
+        .unwrap_or(without_scheme)
+}
+
+/// Checks the configuration for problems that would otherwise only surface at request time
+/// (e.g. an unreachable JWKS endpoint). Invalid values are reported when the handler is built.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigCheck {
+    jwks_uri: Option<Url>,
+    jwks_pre_cached_file_path: Option<PathBuf>,
+    accepted_issuers: Vec<String>,
+    accepted_audiences: Vec<String>,
+    denylist_url: Option<Url>,
+}
+
+impl ConfigCheck {
+    pub fn new(
+        jwks_uri: Option<Url>,
+        jwks_pre_cached_file_path: Option<PathBuf>,
+        accepted_issuers: &str,
+        accepted_audiences: &str,
+    ) -> Self {
+        Self {
+            jwks_uri,
+            jwks_pre_cached_file_path,
+            accepted_issuers: split_comma_separated_values(accepted_issuers),
+            accepted_audiences: split_comma_separated_values(accepted_audiences),
+            denylist_url: None,
+        }
+    }
+
+    pub fn with_denylist_url(mut self, denylist_url: Option<Url>) -> Self {
+        self.denylist_url = denylist_url;
+        self
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            config
+                .var("JWKS_URI")
+                .ok()
+                .and_then(|jwks_uri| jwks_uri.parse().ok()),
+            config
+                .var("JWKS_PRE_CACHED_FILE_PATH")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from),
+            &config.var("ACCEPTED_ISSUERS").unwrap_or_default(),
+            &config.var("ACCEPTED_AUDIENCES").unwrap_or_default(),
+        )
+        .with_denylist_url(
+            config
+                .var("DENYLIST_URL")
+                .ok()
+                .and_then(|denylist_url| denylist_url.parse().ok()),
+        )
+    }
+
+    pub async fn run(&self) -> ConfigReport {
+        let mut report = ConfigReport::default();
+
+        let jwks = match &self.jwks_uri {
+            Some(jwks_uri) => self.check_jwks_uri(jwks_uri, &mut report).await,
+            None => None,
+        };
+        self.check_jwks_pre_cached_file(jwks.as_ref(), &mut report);
+        self.check_accepted_issuers(&mut report);
+        if let Some(denylist_url) = &self.denylist_url {
+            self.check_denylist_url(denylist_url, &mut report).await;
+        }
+        if self.accepted_audiences.is_empty() {
+            report.warning(
+                Some("ACCEPTED_AUDIENCES"),
+                "No accepted audience is configured: tokens issued for any audience are accepted",
+            );
+        }
+
+        report
+    }
+
+    /// Revoked tokens are denied until the denylist is fetched, so an unreachable denylist
+    /// would deny every request
+    async fn check_denylist_url(&self, denylist_url: &Url, report: &mut ConfigReport) {
+        let client = Client::builder()
+            .user_agent(format!("oidc-authorizer/{}", env!("CARGO_PKG_VERSION")))
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .build()
+            .unwrap();
+        let content = match client.get(denylist_url.as_ref()).send().await {
+            Ok(response) if response.status().is_success() => response.text().await,
+            Ok(response) => {
+                report.error(
+                    Some("DENYLIST_URL"),
+                    format!(
+                        "The denylist URL responded with status {}",
+                        response.status()
+                    ),
+                );
+                return;
+            }
+            Err(e) => Err(e),
+        };
+        let result = content
+            .map_err(|e| format!("The denylist URL is unreachable: {}", e))
+            .and_then(|content| {
+                DenylistEntries::load_from_str(&content).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            report.error(Some("DENYLIST_URL"), e);
+        }
+    }
+
+    async fn check_jwks_uri(&self, jwks_uri: &Url, report: &mut ConfigReport) -> Option<JwkSet> {
+        let client = Client::builder()
+            .user_agent(format!("oidc-authorizer/{}", env!("CARGO_PKG_VERSION")))
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .build()
+            .unwrap();
+        let response = match client.get(jwks_uri.as_ref()).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                report.error(
+                    Some("JWKS_URI"),
+                    format!("The JWKS endpoint is unreachable: {}", e),
+                );
+                return None;
+            }
+        };
+        if !response.status().is_success() {
+            report.error(
+                Some("JWKS_URI"),
+                format!(
+                    "The JWKS endpoint responded with status {}",
+                    response.status()
+                ),
+            );
+            return None;
+        }
+        let jwks = match response.json::<JwkSet>().await {
+            Ok(jwks) => jwks,
+            Err(e) => {
+                report.error(
+                    Some("JWKS_URI"),
+                    format!("The JWKS endpoint did not return a valid JWKS: {}", e),
+                );
+                return None;
+            }
+        };
+
+        let usable_keys = KeysMap::from(jwks.clone()).len();
+        if usable_keys == 0 {
+            report.error(Some("JWKS_URI"), "The JWKS does not contain any usable key");
+        } else if usable_keys < jwks.keys.len() {
+            report.warning(
+                Some("JWKS_URI"),
+                format!(
+                    "{} of the {} keys of the JWKS cannot be used (missing kid or unsupported key)",
+                    jwks.keys.len() - usable_keys,
+                    jwks.keys.len()
+                ),
+            );
+        }
+        Some(jwks)
+    }
+
+    fn check_jwks_pre_cached_file(&self, jwks: Option<&JwkSet>, report: &mut ConfigReport) {
+        let Some(path) = &self.jwks_pre_cached_file_path else {
+            return;
+        };
+        let pre_cached_jwks = match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| serde_json::from_str::<JwkSet>(&content).map_err(|e| e.to_string()))
+        {
+            Ok(pre_cached_jwks) => pre_cached_jwks,
+            Err(e) => {
+                report.error(
+                    Some("JWKS_PRE_CACHED_FILE_PATH"),
+                    format!(
+                        "Failed to load the pre-cached JWKS file '{}': {}",
+                        path.display(),
+                        e
+                    ),
+                );
+                return;
+            }
+        };
+
+        let Some(jwks) = jwks else {
+            return;
+        };
+        let key_ids = |jwks: &JwkSet| -> HashSet<String> {
+            jwks.keys
+                .iter()
+                .filter_map(|key| key.common.key_id.clone())
+                .collect()
+        };
+        let mut missing_key_ids: Vec<_> = key_ids(jwks)
+            .difference(&key_ids(&pre_cached_jwks))
+            .cloned()
+            .collect();
+        missing_key_ids.sort();
+        if !missing_key_ids.is_empty() {
+            report.warning(
+                Some("JWKS_PRE_CACHED_FILE_PATH"),
+                format!(
+                    "The pre-cached JWKS file is outdated: the keys [{}] are missing",
+                    missing_key_ids.join(", ")
+                ),
+            );
+        }
+    }
+
+    fn check_accepted_issuers(&self, report: &mut ConfigReport) {
+        if self.accepted_issuers.is_empty() {
+            report.warning(
+                Some("ACCEPTED_ISSUERS"),
+                "No accepted issuer is configured: tokens signed with the JWKS keys are accepted from any issuer",
+            );
+            return;
+        }
+
+        let Some(jwks_host) = self.jwks_uri.as_ref().and_then(Url::host_str) else {
+            return;
+        };
+        let matches_jwks_host = |issuer: &String| {
+            let host = issuer_host(issuer).replace(TENANT_PLACEHOLDER, "*");
+            glob_match(&host, jwks_host)
+        };
+        if !self.accepted_issuers.iter().any(matches_jwks_host) {
+            report.warning(
+                Some("ACCEPTED_ISSUERS"),
+                format!(
+                    "None of the accepted issuers matches the host of the JWKS URI ('{}')",
+                    jwks_host
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn jwks() -> String {
+        format!(
+            "{{\"keys\":[ {} ]}}",
+            include_str!("../tests/fixtures/keys/rs256/jwk.json")
+        )
+    }
+
+    #[tokio::test]
+    async fn it_should_accept_a_valid_configuration() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200).body(jwks());
+        });
+        let mut pre_cached_file = NamedTempFile::new().unwrap();
+        write!(pre_cached_file, "{}", jwks()).unwrap();
+
+        let report = ConfigCheck::new(
+            server.url("/").parse().ok(),
+            Some(pre_cached_file.path().to_path_buf()),
+            &format!("https://idp.example.com, http://{}", server.host()),
+            "api",
+        )
+        .run()
+        .await;
+
+        assert!(report.issues().is_empty(), "{}", report);
+        assert_eq!(report.to_string(), "0 error(s), 0 warning(s)");
+    }
+
+    #[test]
+    fn it_should_report_every_invalid_setting() {
+        let mut report = ConfigReport::default();
+        assert_eq!(report.check("DPOP_MAX_AGE", "300".parse::<u64>()), 300);
+        assert_eq!(report.check("MIN_REFRESH_RATE", "abc".parse::<u64>()), 0);
+        assert_eq!(
+            report.check("JWKS_URI", "not a url".parse::<Url>().map(Some)),
+            None
+        );
+
+        assert!(report.has_errors());
+        let settings: Vec<_> = report.issues().iter().map(|issue| issue.setting).collect();
+        assert_eq!(settings, [Some("MIN_REFRESH_RATE"), Some("JWKS_URI")]);
+    }
+
+    #[tokio::test]
+    async fn it_should_report_jwks_errors() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/missing");
+            then.status(404);
+        });
+        let _empty_jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/empty");
+            then.status(200).body("{\"keys\": []}");
+        });
+
+        for (jwks_uri, message) in [
+            ("http://127.0.0.1:1/".to_string(), "unreachable"),
+            (server.url("/missing"), "responded with status 404"),
+            (server.url("/empty"), "does not contain any usable key"),
+        ] {
+            let report = ConfigCheck::new(jwks_uri.parse().ok(), None, "", "api")
+                .run()
+                .await;
+            assert!(report.has_errors());
+            assert!(report.issues()[0].message.contains(message), "{}", report);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_report_denylist_errors() {
+        let server = MockServer::start();
+        let _denylist_mock = server.mock(|when, then| {
+            when.method(GET).path("/denylist.json");
+            then.status(200).body(r#"{"entries": []}"#);
+        });
+        let _invalid_denylist_mock = server.mock(|when, then| {
+            when.method(GET).path("/invalid.json");
+            then.status(200)
+                .body(r#"{"entries": [{"claim": "email"}]}"#);
+        });
+        let _missing_denylist_mock = server.mock(|when, then| {
+            when.method(GET).path("/missing.json");
+            then.status(404);
+        });
+
+        let report = ConfigCheck::new(None, None, "", "api")
+            .with_denylist_url(server.url("/denylist.json").parse().ok())
+            .run()
+            .await;
+        assert!(!report.has_errors(), "{}", report);
+
+        for (denylist_url, message) in [
+            ("http://127.0.0.1:1/".to_string(), "unreachable"),
+            (server.url("/missing.json"), "responded with status 404"),
+            (server.url("/invalid.json"), "Invalid denylist entries"),
+        ] {
+            let report = ConfigCheck::new(None, None, "", "api")
+                .with_denylist_url(denylist_url.parse().ok())
+                .run()
+                .await;
+            let issue = report
+                .issues()
+                .iter()
+                .find(|issue| issue.severity == IssueSeverity::Error)
+                .unwrap();
+            assert_eq!(issue.setting, Some("DENYLIST_URL"));
+            assert!(issue.message.contains(message), "{}", report);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_report_pre_cached_file_and_issuer_problems() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200).body(jwks());
+        });
+        let mut pre_cached_file = NamedTempFile::new().unwrap();
+        write!(pre_cached_file, "{{\"keys\": []}}").unwrap();
+
+        let report = ConfigCheck::new(
+            server.url("/").parse().ok(),
+            Some(pre_cached_file.path().to_path_buf()),
+            "https://{tenant}.idp.example.com/",
+            "",
+        )
+        .run()
+        .await;
+        assert!(!report.has_errors());
+        assert_eq!(
+            report
+                .issues()
+                .iter()
+                .map(|issue| issue.setting.unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                "JWKS_PRE_CACHED_FILE_PATH",
+                "ACCEPTED_ISSUERS",
+                "ACCEPTED_AUDIENCES"
+            ]
+        );
+
+        let report = ConfigCheck::new(
+            server.url("/").parse().ok(),
+            Some(PathBuf::from("/does/not/exist.json")),
+            "",
+            "api",
+        )
+        .run()
+        .await;
+        assert_eq!(
+            report.issues()[0].severity,
+            IssueSeverity::Error,
+            "{}",
+            report
+        );
+        assert_eq!(report.issues()[1].setting, Some("ACCEPTED_ISSUERS"));
+    }
+
+    #[test]
+    fn it_should_extract_the_issuer_host() {
+        assert_eq!(issuer_host("https://idp.example.com/"), "idp.example.com");
+        assert_eq!(issuer_host("http://localhost:8080/realms/a"), "localhost");
+        assert_eq!(
+            issuer_host("https://login.microsoftonline.com/{tenant}/v2.0"),
+            "login.microsoftonline.com"
+        );
+        assert_eq!(issuer_host("*.eu.auth0.com"), "*.eu.auth0.com");
+    }
+}
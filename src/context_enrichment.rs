@@ -0,0 +1,386 @@
+use crate::{
+    cel_validation::{CelExpression, CelValidationError},
+    claim_path,
+    models::RequestMetadata,
+};
+use jsonwebtoken::Header;
+use serde_json::Value;
+use std::{collections::HashMap, str::FromStr};
+use thiserror::Error;
+
+static DEFAULT_ARRAY_SEPARATOR: &str = ",";
+
+#[derive(Debug, Error)]
+pub enum ContextMappingError {
+    #[error(
+        "Invalid context claim mapping '{0}'. Expected format: 'claim.path->contextKey[:type]'"
+    )]
+    Malformed(String),
+    #[error("Unsupported context value type '{0}'. Supported values: string, number, boolean")]
+    UnsupportedType(String),
+}
+
+/// The type a claim is converted to before being added to the context.
+/// API Gateway only supports string, number and boolean context values.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ContextValueType {
+    /// Strings, numbers and booleans are kept as they are, arrays are joined
+    /// and objects are converted to their JSON representation
+    #[default]
+    Auto,
+    String,
+    Number,
+    Boolean,
+}
+
+impl FromStr for ContextValueType {
+    type Err = ContextMappingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "auto" => Ok(ContextValueType::Auto),
+            "string" => Ok(ContextValueType::String),
+            "number" => Ok(ContextValueType::Number),
+            "boolean" | "bool" => Ok(ContextValueType::Boolean),
+            _ => Err(ContextMappingError::UnsupportedType(s.to_string())),
+        }
+    }
+}
+
+/// Maps a (possibly nested) claim to an individual context key
+/// (e.g. `realm_access.roles->roles` or `age->age:number`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextClaim {
+    path: String,
+    key: String,
+    value_type: ContextValueType,
+}
+
+impl FromStr for ContextClaim {
+    type Err = ContextMappingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, target) = s.split_once("->").unwrap_or((s, ""));
+        let (key, value_type) = target.split_once(':').unwrap_or((target, ""));
+        let path = path.trim();
+        // without an explicit key, the last segment of the path is used
+        let key = match key.trim() {
+            "" => path.rsplit(['.', '/']).next().unwrap_or_default(),
+            key => key,
+        };
+        if path.is_empty() || key.is_empty() {
+            return Err(ContextMappingError::Malformed(s.to_string()));
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            key: key.to_string(),
+            value_type: value_type.parse()?,
+        })
+    }
+}
+
+impl ContextClaim {
+    pub fn from_comma_separated_values(
+        comma_separated_values: &str,
+    ) -> Result<Vec<Self>, ContextMappingError> {
+        comma_separated_values
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    /// Converts the claim to a value supported by API Gateway (if possible)
+    fn to_context_value(&self, claim: &Value, array_separator: &str) -> Option<Value> {
+        let value = match claim {
+            Value::Null => return None,
+            Value::Array(items) => Value::String(
+                items
+                    .iter()
+                    .map(claim_path::to_string)
+                    .collect::<Vec<_>>()
+                    .join(array_separator),
+            ),
+            Value::Object(_) => Value::String(claim.to_string()),
+            value => value.clone(),
+        };
+
+        match (self.value_type, value) {
+            (ContextValueType::Auto, value) => Some(value),
+            (ContextValueType::String, Value::String(s)) => Some(Value::String(s)),
+            (ContextValueType::String, value) => Some(Value::String(value.to_string())),
+            (ContextValueType::Number, Value::Number(n)) => Some(Value::Number(n)),
+            (ContextValueType::Number, Value::String(s)) => match s.trim().parse::<i64>() {
+                Ok(n) => Some(n.into()),
+                Err(_) => s.trim().parse::<f64>().ok().map(Value::from),
+            },
+            (ContextValueType::Boolean, Value::Bool(b)) => Some(Value::Bool(b)),
+            (ContextValueType::Boolean, Value::String(s)) => {
+                match s.trim().to_lowercase().as_str() {
+                    "true" => Some(Value::Bool(true)),
+                    "false" => Some(Value::Bool(false)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Computes the additional values to add to the authorizer response context
+#[derive(Debug, Clone)]
+pub struct ContextEnrichment {
+    cel: Option<CelExpression>,
+    claims: Vec<ContextClaim>,
+    array_separator: String,
+    include_jwt_claims: bool,
+    tenant_key: Option<String>,
+}
+
+impl Default for ContextEnrichment {
+    fn default() -> Self {
+        Self {
+            cel: None,
+            claims: Vec::new(),
+            array_separator: DEFAULT_ARRAY_SEPARATOR.to_string(),
+            include_jwt_claims: true,
+            tenant_key: None,
+        }
+    }
+}
+
+impl ContextEnrichment {
+    /// Adds the given claims to the context as individual keys
+    pub fn with_claims(mut self, claims: Vec<ContextClaim>) -> Self {
+        self.claims = claims;
+        self
+    }
+
+    /// Sets the separator used to join array claims
+    pub fn with_array_separator(mut self, array_separator: String) -> Self {
+        self.array_separator = array_separator;
+        self
+    }
+
+    /// Whether the whole token payload is added to the context as `jwtClaims`
+    pub fn with_jwt_claims(mut self, include_jwt_claims: bool) -> Self {
+        self.include_jwt_claims = include_jwt_claims;
+        self
+    }
+
+    pub fn include_jwt_claims(&self) -> bool {
+        self.include_jwt_claims
+    }
+
+    /// Adds the tenant captured by the issuer (or audience) patterns to the context using the
+    /// given key (an empty key disables it)
+    pub fn with_tenant_key(mut self, tenant_key: String) -> Self {
+        self.tenant_key = Some(tenant_key.trim().to_string()).filter(|key| !key.is_empty());
+        self
+    }
+
+    pub fn tenant_key(&self) -> Option<&str> {
+        self.tenant_key.as_deref()
+    }
+
+    /// Uses a CEL expression returning a map to compute additional context values.
+    /// Non-string values are converted to their JSON representation.
+    pub fn with_cel(mut self, cel: CelExpression) -> Self {
+        self.cel = Some(cel);
+        self
+    }
+
+    /// Returns `true` if the context depends on the request (and not only on the token)
+    pub fn is_request_dependent(&self) -> bool {
+        self.cel
+            .as_ref()
+            .is_some_and(CelExpression::is_request_dependent)
+    }
+
+    pub fn build(
+        &self,
+        header: Option<&Header>,
+        claims: &Value,
+        request: &RequestMetadata,
+    ) -> Result<HashMap<String, Value>, CelValidationError> {
+        let mut context = HashMap::new();
+
+        for claim in &self.claims {
+            let Some(value) = claim_path::lookup(claims, &claim.path) else {
+                continue;
+            };
+            match claim.to_context_value(value, &self.array_separator) {
+                Some(value) => {
+                    context.insert(claim.key.clone(), value);
+                }
+                None => tracing::debug!(
+                    "Cannot convert the claim '{}' to a {:?} context value",
+                    claim.path,
+                    claim.value_type
+                ),
+            }
+        }
+
+        if let Some(cel) = &self.cel {
+            match cel.evaluate(header, claims, request)? {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        let value = match value {
+                            Value::String(s) => s,
+                            value => value.to_string(),
+                        };
+                        context.insert(key, Value::String(value));
+                    }
+                }
+                value => {
+                    return Err(CelValidationError::UnexpectedResult(format!(
+                        "the context expression '{}' must return a map (got '{}')",
+                        cel.expression(),
+                        value
+                    )))
+                }
+            }
+        }
+
+        Ok(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_should_build_an_empty_context_by_default() {
+        let context_enrichment = ContextEnrichment::default();
+        let context = context_enrichment
+            .build(
+                Some(&Header::default()),
+                &json!({"sub": "user123"}),
+                &RequestMetadata::default(),
+            )
+            .unwrap();
+        assert!(context.is_empty());
+    }
+
+    #[test]
+    fn it_should_build_the_context_from_a_cel_expression() {
+        let context_enrichment = ContextEnrichment::default().with_cel(
+            r#"{"userId": claims.sub, "tenant": claims.tenant, "admin": "admin" in claims.roles, "level": claims.level}"#
+                .parse()
+                .unwrap(),
+        );
+        let context = context_enrichment
+            .build(
+                Some(&Header::default()),
+                &json!({"sub": "user123", "tenant": "acme", "roles": ["admin"], "level": 3}),
+                &RequestMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            context,
+            HashMap::from([
+                ("userId".to_string(), json!("user123")),
+                ("tenant".to_string(), json!("acme")),
+                ("admin".to_string(), json!("true")),
+                ("level".to_string(), json!("3")),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_should_parse_claim_mappings() {
+        let claims = ContextClaim::from_comma_separated_values(
+            "sub->userId, realm_access.roles, age->age:number, ",
+        )
+        .unwrap();
+        assert_eq!(
+            claims,
+            vec![
+                ContextClaim {
+                    path: "sub".to_string(),
+                    key: "userId".to_string(),
+                    value_type: ContextValueType::Auto,
+                },
+                ContextClaim {
+                    path: "realm_access.roles".to_string(),
+                    key: "roles".to_string(),
+                    value_type: ContextValueType::Auto,
+                },
+                ContextClaim {
+                    path: "age".to_string(),
+                    key: "age".to_string(),
+                    value_type: ContextValueType::Number,
+                },
+            ]
+        );
+        assert!(matches!(
+            ContextClaim::from_comma_separated_values("->userId"),
+            Err(ContextMappingError::Malformed(_))
+        ));
+        assert!(matches!(
+            ContextClaim::from_comma_separated_values("sub->userId:date"),
+            Err(ContextMappingError::UnsupportedType(_))
+        ));
+    }
+
+    #[test]
+    fn it_should_build_the_context_from_claims() {
+        let context_enrichment = ContextEnrichment::default()
+            .with_claims(
+                ContextClaim::from_comma_separated_values(
+                    "sub->userId, realm_access.roles->roles, groups.1->secondGroup, address, \
+                     https://example.com/tenant->tenant, age->age:number, age->ageString:string, \
+                     email_verified->verified:boolean, missing->missing, name->invalid:number",
+                )
+                .unwrap(),
+            )
+            .with_array_separator(" ".to_string());
+        let context = context_enrichment
+            .build(
+                Some(&Header::default()),
+                &json!({
+                    "sub": "user123",
+                    "name": "John",
+                    "realm_access": { "roles": ["admin", "user"] },
+                    "groups": ["a", "b"],
+                    "address": { "country": "IT" },
+                    "https://example.com/tenant": "acme",
+                    "age": "42",
+                    "email_verified": "true",
+                }),
+                &RequestMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            context,
+            HashMap::from([
+                ("userId".to_string(), json!("user123")),
+                ("roles".to_string(), json!("admin user")),
+                ("secondGroup".to_string(), json!("b")),
+                ("address".to_string(), json!("{\"country\":\"IT\"}")),
+                ("tenant".to_string(), json!("acme")),
+                ("age".to_string(), json!(42)),
+                ("ageString".to_string(), json!("42")),
+                ("verified".to_string(), json!(true)),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_should_fail_if_the_cel_expression_does_not_return_a_map() {
+        let context_enrichment =
+            ContextEnrichment::default().with_cel("claims.sub".parse().unwrap());
+        let result = context_enrichment.build(
+            Some(&Header::default()),
+            &json!({"sub": "user123"}),
+            &RequestMetadata::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(CelValidationError::UnexpectedResult(_))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{
+    cel_validation::CelValidationError,
+    certificate_binding::CertificateBindingError,
+    denylist::DenylistClaim,
+    dpop::DpopError,
+    introspection::IntrospectionError,
+    keys_storage::{KeysStorageError, KeysStorageStats},
+    rbac::RbacError,
+};
+use jsonwebtoken::{errors::ErrorKind, Algorithm};
+use std::{fmt, time::Duration};
+
+/// The reason why a request was denied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DenyReason {
+    /// The request does not contain an authorization header (or token)
+    MissingHeader,
+    /// The authorization header does not use the `Bearer` scheme
+    MalformedScheme,
+    /// The token header cannot be decoded
+    UndecodableHeader,
+    /// The token is encrypted (JWE) but it cannot be decrypted with the configured keys
+    UndecryptableToken,
+    AlgorithmNotAccepted,
+    /// The token header has no `kid`
+    MissingKid,
+    /// No key with the given `kid` exists in the JWKS
+    UnknownKid,
+    /// The JWKS could not be fetched or parsed
+    JwksUnavailable,
+    Expired,
+    NotYetValid,
+    BadIssuer,
+    BadAudience,
+    /// The client (`azp`, `client_id` or `cid` claim) is missing or not in `ACCEPTED_CLIENT_IDS`
+    BadClientId,
+    BadSignature,
+    /// The introspection endpoint reports that the token is not active
+    InactiveToken,
+    /// The token could not be introspected (e.g. the introspection endpoint is unavailable)
+    IntrospectionFailed,
+    /// The introspection endpoint did not respond within `INTROSPECTION_TIMEOUT_MS`
+    IntrospectionTimeout,
+    /// The DPoP proof is missing or invalid
+    InvalidDpopProof,
+    /// The DPoP proof has already been used
+    DpopProofReplayed,
+    /// The token is not bound to the key of the DPoP proof (or a DPoP-bound token is used as a bearer token)
+    DpopBindingMismatch,
+    /// The token is bound to a client certificate but the request has no (valid) client certificate
+    MissingClientCertificate,
+    /// The client certificate does not match the certificate the token is bound to
+    CertificateBindingMismatch,
+    /// The token has been revoked (a denylist entry matches the given claim)
+    Revoked {
+        claim: DenylistClaim,
+    },
+    /// The denylist URL could not be fetched (yet), so revoked tokens cannot be detected
+    DenylistUnavailable,
+    /// The token does not have the roles required by the given route
+    InsufficientRoles {
+        route: String,
+    },
+    /// The token is invalid for any other reason (e.g. malformed payload or missing claims)
+    InvalidToken,
+    /// The token was rejected by the CEL expression or by the given CEL rule
+    CelRejected {
+        rule: Option<String>,
+    },
+    /// The principal ID could not be computed
+    PrincipalIdError,
+    /// The token does not contain the claim required for the usage identifier key
+    MissingUsageIdentifierKey,
+    /// The authorizer context could not be computed
+    ContextError,
+    /// The authorizer response exceeds the configured size limit (even after reducing the context)
+    ResponseTooLarge,
+}
+
+impl DenyReason {
+    /// A stable name for the reason (without details), suitable for metrics and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            DenyReason::MissingHeader => "MissingHeader",
+            DenyReason::MalformedScheme => "MalformedScheme",
+            DenyReason::UndecodableHeader => "UndecodableHeader",
+            DenyReason::UndecryptableToken => "UndecryptableToken",
+            DenyReason::AlgorithmNotAccepted => "AlgorithmNotAccepted",
+            DenyReason::MissingKid => "MissingKid",
+            DenyReason::UnknownKid => "UnknownKid",
+            DenyReason::JwksUnavailable => "JwksUnavailable",
+            DenyReason::Expired => "Expired",
+            DenyReason::NotYetValid => "NotYetValid",
+            DenyReason::BadIssuer => "BadIssuer",
+            DenyReason::BadAudience => "BadAudience",
+            DenyReason::BadClientId => "BadClientId",
+            DenyReason::BadSignature => "BadSignature",
+            DenyReason::InactiveToken => "InactiveToken",
+            DenyReason::IntrospectionFailed => "IntrospectionFailed",
+            DenyReason::IntrospectionTimeout => "IntrospectionTimeout",
+            DenyReason::InvalidDpopProof => "InvalidDpopProof",
+            DenyReason::DpopProofReplayed => "DpopProofReplayed",
+            DenyReason::DpopBindingMismatch => "DpopBindingMismatch",
+            DenyReason::MissingClientCertificate => "MissingClientCertificate",
+            DenyReason::CertificateBindingMismatch => "CertificateBindingMismatch",
+            DenyReason::Revoked { .. } => "Revoked",
+            DenyReason::DenylistUnavailable => "DenylistUnavailable",
+            DenyReason::InsufficientRoles { .. } => "InsufficientRoles",
+            DenyReason::InvalidToken => "InvalidToken",
+            DenyReason::CelRejected { .. } => "CelRejected",
+            DenyReason::PrincipalIdError => "PrincipalIdError",
+            DenyReason::MissingUsageIdentifierKey => "MissingUsageIdentifierKey",
+            DenyReason::ContextError => "ContextError",
+            DenyReason::ResponseTooLarge => "ResponseTooLarge",
+        }
+    }
+}
+
+impl fmt::Display for DenyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DenyReason::CelRejected { rule: Some(rule) } => write!(f, "CelRejected({})", rule),
+            DenyReason::Revoked { claim } => write!(f, "Revoked({})", claim),
+            DenyReason::InsufficientRoles { route } => write!(f, "InsufficientRoles({})", route),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl From<&jsonwebtoken::errors::Error> for DenyReason {
+    fn from(e: &jsonwebtoken::errors::Error) -> Self {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => DenyReason::Expired,
+            ErrorKind::ImmatureSignature => DenyReason::NotYetValid,
+            ErrorKind::InvalidIssuer => DenyReason::BadIssuer,
+            ErrorKind::InvalidAudience => DenyReason::BadAudience,
+            ErrorKind::InvalidSignature => DenyReason::BadSignature,
+            _ => DenyReason::InvalidToken,
+        }
+    }
+}
+
+impl From<&KeysStorageError> for DenyReason {
+    fn from(e: &KeysStorageError) -> Self {
+        match e {
+            KeysStorageError::KeyNotFound(_) => DenyReason::UnknownKid,
+            KeysStorageError::FetchError(_) | KeysStorageError::JwksParseError(_) => {
+                DenyReason::JwksUnavailable
+            }
+        }
+    }
+}
+
+impl From<&IntrospectionError> for DenyReason {
+    fn from(e: &IntrospectionError) -> Self {
+        match e {
+            IntrospectionError::Inactive => DenyReason::InactiveToken,
+            IntrospectionError::Timeout => DenyReason::IntrospectionTimeout,
+            _ => DenyReason::IntrospectionFailed,
+        }
+    }
+}
+
+impl From<&DpopError> for DenyReason {
+    fn from(e: &DpopError) -> Self {
+        match e {
+            DpopError::UnsupportedScheme(_) => DenyReason::MalformedScheme,
+            DpopError::Replayed(_) => DenyReason::DpopProofReplayed,
+            DpopError::UnboundToken | DpopError::BoundTokenAsBearer | DpopError::KeyMismatch => {
+                DenyReason::DpopBindingMismatch
+            }
+            _ => DenyReason::InvalidDpopProof,
+        }
+    }
+}
+
+impl From<&CertificateBindingError> for DenyReason {
+    fn from(e: &CertificateBindingError) -> Self {
+        match e {
+            CertificateBindingError::UnboundToken | CertificateBindingError::Mismatch => {
+                DenyReason::CertificateBindingMismatch
+            }
+            _ => DenyReason::MissingClientCertificate,
+        }
+    }
+}
+
+impl From<&RbacError> for DenyReason {
+    fn from(e: &RbacError) -> Self {
+        match e {
+            RbacError::AccessDenied { route, .. } => DenyReason::InsufficientRoles {
+                route: route.clone(),
+            },
+        }
+    }
+}
+
+impl From<&CelValidationError> for DenyReason {
+    fn from(e: &CelValidationError) -> Self {
+        match e {
+            CelValidationError::RuleFailed { rule, .. } => DenyReason::CelRejected {
+                rule: Some(rule.clone()),
+            },
+            _ => DenyReason::CelRejected { rule: None },
+        }
+    }
+}
+
+/// The record of a single authorization decision
+#[derive(Debug, Clone, Default)]
+pub struct Decision {
+    /// The reason why the request was denied (`None` if the request was allowed)
+    pub deny_reason: Option<DenyReason>,
+    /// The fingerprint of the token (see [`crate::log_redaction::token_fingerprint`])
+    pub token_fingerprint: Option<String>,
+    pub kid: Option<String>,
+    pub alg: Option<Algorithm>,
+    /// The (validated) issuer of the token
+    pub issuer: Option<String>,
+    pub subject: Option<String>,
+    /// The client the token was issued to (`client_id` or `azp` claim)
+    pub client_id: Option<String>,
+    pub jti: Option<String>,
+    pub stage: String,
+    /// The X-Ray trace header of the invocation
+    pub xray_trace_id: Option<String>,
+    pub latency: Duration,
+    pub jwks: KeysStorageStats,
+}
+
+impl Decision {
+    pub fn effect(&self) -> &'static str {
+        match self.deny_reason {
+            Some(_) => "Deny",
+            None => "Allow",
+        }
+    }
+
+    /// Emits the structured log event describing the decision
+    pub fn log(&self) {
+        let rule = match &self.deny_reason {
+            Some(DenyReason::CelRejected { rule }) => rule.as_deref(),
+            _ => None,
+        };
+        tracing::info!(
+            event_type = "authorization_decision",
+            decision = self.effect(),
+            reason = self.deny_reason.as_ref().map(DenyReason::name),
+            rule = rule,
+            token_fingerprint = self.token_fingerprint.as_deref(),
+            kid = self.kid.as_deref(),
+            iss = self.issuer.as_deref(),
+            alg = self.alg.map(|alg| format!("{:?}", alg)),
+            stage = self.stage,
+            xray_trace_id = self.xray_trace_id.as_deref(),
+            latency_ms = self.latency.as_secs_f64() * 1000.0,
+            "Request {}",
+            match &self.deny_reason {
+                Some(reason) => format!("denied ({})", reason),
+                None => "allowed".to_string(),
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_test::traced_test;
+
+    #[test]
+    fn it_should_map_jwt_errors_to_deny_reasons() {
+        let cases = [
+            (ErrorKind::ExpiredSignature, DenyReason::Expired),
+            (ErrorKind::ImmatureSignature, DenyReason::NotYetValid),
+            (ErrorKind::InvalidIssuer, DenyReason::BadIssuer),
+            (ErrorKind::InvalidAudience, DenyReason::BadAudience),
+            (ErrorKind::InvalidSignature, DenyReason::BadSignature),
+            (ErrorKind::InvalidToken, DenyReason::InvalidToken),
+        ];
+        for (kind, expected) in cases {
+            let error = jsonwebtoken::errors::Error::from(kind);
+            assert_eq!(DenyReason::from(&error), expected);
+        }
+    }
+
+    #[test]
+    fn it_should_map_keys_storage_errors_to_deny_reasons() {
+        let error = KeysStorageError::KeyNotFound("some-kid".to_string());
+        assert_eq!(DenyReason::from(&error), DenyReason::UnknownKid);
+        let error = KeysStorageError::JwksParseError(
+            serde_json::from_str::<serde_json::Value>("{").unwrap_err(),
+        );
+        assert_eq!(DenyReason::from(&error), DenyReason::JwksUnavailable);
+    }
+
+    #[test]
+    fn it_should_map_cel_errors_to_deny_reasons() {
+        let error = CelValidationError::RuleFailed {
+            rule: "email-verified".to_string(),
+            description: "".to_string(),
+            source: Box::new(CelValidationError::NonBooleanResult),
+        };
+        let reason = DenyReason::from(&error);
+        assert_eq!(
+            reason,
+            DenyReason::CelRejected {
+                rule: Some("email-verified".to_string())
+            }
+        );
+        assert_eq!(reason.name(), "CelRejected");
+        assert_eq!(reason.to_string(), "CelRejected(email-verified)");
+        assert_eq!(
+            DenyReason::from(&CelValidationError::NonBooleanResult),
+            DenyReason::CelRejected { rule: None }
+        );
+    }
+
+    #[test]
+    #[traced_test]
+    fn it_should_log_a_structured_decision() {
+        let decision = Decision {
+            deny_reason: Some(DenyReason::BadAudience),
+            kid: Some("some-kid".to_string()),
+            alg: Some(Algorithm::RS256),
+            ..Default::default()
+        };
+        assert_eq!(decision.effect(), "Deny");
+        decision.log();
+
+        assert!(logs_contain("event_type=\"authorization_decision\""));
+        assert!(logs_contain("decision=\"Deny\""));
+        assert!(logs_contain("reason=\"BadAudience\""));
+        assert!(logs_contain("kid=\"some-kid\""));
+        assert!(logs_contain("alg=\"RS256\""));
+    }
+}
@@ -0,0 +1,426 @@
+use chrono::{DateTime, Duration, Utc};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, fmt, path::Path, sync::Arc, time};
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+/// How long to wait for the denylist URL. The fetch happens while a request is being authorized.
+const DENYLIST_FETCH_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+#[derive(Debug, Error)]
+pub enum DenylistError {
+    #[error("Failed to read denylist file '{0}': {1}")]
+    FileError(String, std::io::Error),
+    #[error("Failed to fetch denylist: {0}")]
+    FetchError(#[from] reqwest::Error),
+    #[error("Invalid denylist entries: {0}")]
+    InvalidEntries(String),
+    #[error("The denylist has not been fetched yet")]
+    Unavailable,
+}
+
+/// The claim a denylist entry matches against
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DenylistClaim {
+    Jti,
+    Sub,
+    Sid,
+    /// Matches the `client_id` claim or, if missing, the `azp` claim
+    ClientId,
+}
+
+impl DenylistClaim {
+    fn value_in<'a>(&self, claims: &'a Value) -> Option<&'a str> {
+        match self {
+            DenylistClaim::Jti => claims["jti"].as_str(),
+            DenylistClaim::Sub => claims["sub"].as_str(),
+            DenylistClaim::Sid => claims["sid"].as_str(),
+            DenylistClaim::ClientId => claims["client_id"]
+                .as_str()
+                .or_else(|| claims["azp"].as_str()),
+        }
+    }
+}
+
+impl fmt::Display for DenylistClaim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DenylistClaim::Jti => write!(f, "jti"),
+            DenylistClaim::Sub => write!(f, "sub"),
+            DenylistClaim::Sid => write!(f, "sid"),
+            DenylistClaim::ClientId => write!(f, "client_id"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DenylistFile {
+    entries: Vec<DenylistEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DenylistEntry {
+    claim: DenylistClaim,
+    value: String,
+    /// When the entry expires (seconds since the Unix epoch). This is generally the `exp` of the
+    /// revoked token, since after that the token is rejected anyway. Entries without `exp` never expire.
+    exp: Option<i64>,
+}
+
+/// Maps (claim, value) pairs to the expiration of the entry
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DenylistEntries(HashMap<(DenylistClaim, String), Option<i64>>);
+
+impl DenylistEntries {
+    /// Parses a list of entries from a JSON or YAML string (YAML is a superset of JSON)
+    pub fn load_from_str(content: &str) -> Result<Self, DenylistError> {
+        let file: DenylistFile = serde_yaml::from_str(content)
+            .map_err(|e| DenylistError::InvalidEntries(e.to_string()))?;
+        let now = Utc::now().timestamp();
+
+        Ok(Self(
+            file.entries
+                .into_iter()
+                .filter(|entry| entry.exp.is_none_or(|exp| exp > now))
+                .map(|entry| ((entry.claim, entry.value), entry.exp))
+                .collect(),
+        ))
+    }
+
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, DenylistError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| DenylistError::FileError(path.display().to_string(), e))?;
+        Self::load_from_str(&content)
+    }
+
+    /// Returns the claim that matches a (non expired) entry, if any
+    fn find_match(&self, claims: &Value, now: i64) -> Option<DenylistClaim> {
+        [
+            DenylistClaim::Jti,
+            DenylistClaim::Sub,
+            DenylistClaim::Sid,
+            DenylistClaim::ClientId,
+        ]
+        .into_iter()
+        .find(|claim| {
+            claim.value_in(claims).is_some_and(|value| {
+                self.0
+                    .get(&(*claim, value.to_string()))
+                    .is_some_and(|exp| exp.is_none_or(|exp| exp > now))
+            })
+        })
+    }
+}
+
+/// A list of revoked tokens, loaded from a file and/or periodically refreshed from a URL
+#[derive(Debug, Default)]
+pub struct Denylist {
+    static_entries: DenylistEntries,
+    url: Option<Url>,
+    client: Client,
+    /// The entries from the URL (`None` until the first successful fetch) and when they were fetched
+    storage: Arc<RwLock<(Option<DenylistEntries>, DateTime<Utc>)>>,
+    refresh_rate: Duration,
+}
+
+impl Denylist {
+    pub fn new(static_entries: DenylistEntries, url: Option<Url>, refresh_rate: Duration) -> Self {
+        Self {
+            static_entries,
+            url,
+            client: Client::builder()
+                .user_agent(format!("oidc-authorizer/{}", env!("CARGO_PKG_VERSION")))
+                .timeout(DENYLIST_FETCH_TIMEOUT)
+                .build()
+                .unwrap(),
+            storage: Default::default(),
+            refresh_rate,
+        }
+    }
+
+    /// Returns the claim that matches a denylist entry if the token has been revoked.
+    /// Entries from the URL are refreshed if they are older than the refresh rate. If the refresh
+    /// fails, the last known entries are used (the refresh is retried at the next refresh interval).
+    /// Until the URL has been fetched successfully at least once, the fetch is retried on every
+    /// call and an error is returned if it fails, so that revoked tokens are never accepted
+    /// just because the denylist is unreachable.
+    #[tracing::instrument(name = "denylist.check", skip_all)]
+    pub async fn find_match(&self, claims: &Value) -> Result<Option<DenylistClaim>, DenylistError> {
+        let now = Utc::now();
+        if let Some(claim) = self.static_entries.find_match(claims, now.timestamp()) {
+            return Ok(Some(claim));
+        }
+
+        if let Some(url) = &self.url {
+            let (loaded, should_refresh) = {
+                let read_guard = self.storage.read().await;
+                (
+                    read_guard.0.is_some(),
+                    read_guard.1 + self.refresh_rate < now,
+                )
+            };
+            if !loaded || should_refresh {
+                if let Err(e) = self.refresh(url).await {
+                    if !loaded {
+                        tracing::error!(
+                            event_type = "denylist_refresh_failed",
+                            "Failed to fetch the denylist from '{}': {}",
+                            url,
+                            e
+                        );
+                        return Err(DenylistError::Unavailable);
+                    }
+                    tracing::warn!(
+                        event_type = "denylist_refresh_failed",
+                        "Failed to refresh the denylist from '{}': {}. Using the last known entries.",
+                        url,
+                        e
+                    );
+                    // avoids retrying on every request
+                    self.storage.write().await.1 = now;
+                }
+            }
+
+            return Ok(self
+                .storage
+                .read()
+                .await
+                .0
+                .as_ref()
+                .and_then(|entries| entries.find_match(claims, now.timestamp())));
+        }
+
+        Ok(None)
+    }
+
+    async fn refresh(&self, url: &Url) -> Result<(), DenylistError> {
+        tracing::debug!("Refreshing denylist from '{}'", url);
+        let res = self
+            .client
+            .get(url.as_ref())
+            .send()
+            .await?
+            .error_for_status()?;
+        let entries = DenylistEntries::load_from_str(&res.text().await?)?;
+        tracing::debug!("Denylist fetched got {} entries", entries.0.len());
+
+        let mut write_guard = self.storage.write().await;
+        write_guard.0 = Some(entries);
+        write_guard.1 = Utc::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use serde_json::json;
+    use tracing_test::traced_test;
+
+    #[test]
+    fn it_should_load_entries_and_skip_expired_ones() {
+        let future = Utc::now().timestamp() + 3600;
+        let past = Utc::now().timestamp() - 3600;
+        let entries = DenylistEntries::load_from_str(&format!(
+            r#"
+            entries:
+              - {{ claim: jti, value: token-1, exp: {} }}
+              - {{ claim: jti, value: token-2, exp: {} }}
+              - {{ claim: sub, value: user-1 }}
+            "#,
+            future, past
+        ))
+        .unwrap();
+
+        assert_eq!(entries.0.len(), 2);
+        assert!(DenylistEntries::load_from_str(
+            r#"{"entries": [{"claim": "email", "value": "x"}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn it_should_match_claims() {
+        let now = Utc::now().timestamp();
+        let entries = DenylistEntries::load_from_str(
+            r#"{"entries": [
+                {"claim": "jti", "value": "token-1"},
+                {"claim": "sid", "value": "session-1"},
+                {"claim": "client_id", "value": "client-1"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries.find_match(&json!({"jti": "token-1"}), now),
+            Some(DenylistClaim::Jti)
+        );
+        assert_eq!(
+            entries.find_match(&json!({"jti": "token-2", "sid": "session-1"}), now),
+            Some(DenylistClaim::Sid)
+        );
+        assert_eq!(
+            entries.find_match(&json!({"azp": "client-1"}), now),
+            Some(DenylistClaim::ClientId)
+        );
+        assert_eq!(
+            entries.find_match(&json!({"jti": "token-2", "sub": "token-1"}), now),
+            None
+        );
+    }
+
+    #[test]
+    fn it_should_ignore_entries_that_expired_after_loading() {
+        let exp = Utc::now().timestamp() + 60;
+        let entries = DenylistEntries::load_from_str(&format!(
+            r#"{{"entries": [{{"claim": "jti", "value": "token-1", "exp": {}}}]}}"#,
+            exp
+        ))
+        .unwrap();
+
+        let claims = json!({"jti": "token-1"});
+        assert!(entries.find_match(&claims, exp - 1).is_some());
+        assert!(entries.find_match(&claims, exp).is_none());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_should_refresh_entries_from_a_url() {
+        let server = MockServer::start();
+        let mut denylist_mock = server.mock(|when, then| {
+            when.method(GET).path("/denylist.json");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"entries": [{"claim": "sub", "value": "user-1"}]}"#);
+        });
+
+        let url = Url::parse(&server.url("/denylist.json")).unwrap();
+        let static_entries = DenylistEntries::load_from_str(
+            r#"{"entries": [{"claim": "jti", "value": "token-1"}]}"#,
+        )
+        .unwrap();
+        let denylist = Denylist::new(static_entries, Some(url), Duration::zero());
+
+        // static entries don't need a refresh
+        assert_eq!(
+            denylist
+                .find_match(&json!({"jti": "token-1"}))
+                .await
+                .unwrap(),
+            Some(DenylistClaim::Jti)
+        );
+        denylist_mock.assert_calls(0);
+
+        assert_eq!(
+            denylist
+                .find_match(&json!({"sub": "user-1"}))
+                .await
+                .unwrap(),
+            Some(DenylistClaim::Sub)
+        );
+        denylist_mock.assert_calls(1);
+
+        // a failing refresh keeps the last known entries
+        denylist_mock.delete();
+        let failing_mock = server.mock(|when, then| {
+            when.method(GET).path("/denylist.json");
+            then.status(500);
+        });
+        assert_eq!(
+            denylist
+                .find_match(&json!({"sub": "user-1"}))
+                .await
+                .unwrap(),
+            Some(DenylistClaim::Sub)
+        );
+        failing_mock.assert_calls(1);
+        assert!(logs_contain("denylist_refresh_failed"));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_should_not_refresh_before_the_refresh_rate() {
+        let server = MockServer::start();
+        let denylist_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200).body(r#"{"entries": []}"#);
+        });
+
+        let url = Url::parse(&server.url("/")).unwrap();
+        let denylist = Denylist::new(
+            DenylistEntries::default(),
+            Some(url),
+            Duration::try_seconds(60).unwrap(),
+        );
+
+        assert!(denylist
+            .find_match(&json!({"jti": "token-1"}))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(denylist
+            .find_match(&json!({"jti": "token-2"}))
+            .await
+            .unwrap()
+            .is_none());
+        denylist_mock.assert_calls(1);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_should_fail_closed_until_the_url_is_fetched() {
+        let server = MockServer::start();
+        let mut failing_mock = server.mock(|when, then| {
+            when.method(GET).path("/denylist.json");
+            then.status(500);
+        });
+
+        let url = Url::parse(&server.url("/denylist.json")).unwrap();
+        let static_entries = DenylistEntries::load_from_str(
+            r#"{"entries": [{"claim": "jti", "value": "token-1"}]}"#,
+        )
+        .unwrap();
+        let denylist = Denylist::new(
+            static_entries,
+            Some(url),
+            Duration::try_seconds(60).unwrap(),
+        );
+
+        // static entries still match
+        assert_eq!(
+            denylist
+                .find_match(&json!({"jti": "token-1"}))
+                .await
+                .unwrap(),
+            Some(DenylistClaim::Jti)
+        );
+        // the fetch is retried on every call until it succeeds
+        for _ in 0..2 {
+            assert!(matches!(
+                denylist.find_match(&json!({"jti": "token-2"})).await,
+                Err(DenylistError::Unavailable)
+            ));
+        }
+        failing_mock.assert_calls(2);
+        assert!(logs_contain("denylist_refresh_failed"));
+
+        failing_mock.delete();
+        let denylist_mock = server.mock(|when, then| {
+            when.method(GET).path("/denylist.json");
+            then.status(200).body(r#"{"entries": []}"#);
+        });
+        assert!(denylist
+            .find_match(&json!({"jti": "token-2"}))
+            .await
+            .unwrap()
+            .is_none());
+        denylist_mock.assert_calls(1);
+    }
+}
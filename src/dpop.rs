@@ -0,0 +1,517 @@
+use crate::parse_token_from_header::TokenScheme;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashSet, VecDeque},
+    str::FromStr,
+    sync::Mutex,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DpopError {
+    #[error("Invalid DPoP mode '{0}'. Supported values: disabled, allowed, required")]
+    InvalidMode(String),
+    #[error("The {0:?} authorization scheme is not accepted")]
+    UnsupportedScheme(TokenScheme),
+    #[error("Missing DPoP proof")]
+    MissingProof,
+    #[error("The request contains more than one DPoP proof")]
+    MultipleProofs,
+    #[error("Invalid DPoP proof: {0}")]
+    InvalidProof(String),
+    #[error("The DPoP proof has already been used (jti='{0}')")]
+    Replayed(String),
+    #[error("The DPoP replay cache is full")]
+    ReplayCacheFull,
+    #[error("The token is not bound to a DPoP key (missing cnf.jkt claim)")]
+    UnboundToken,
+    #[error("The token is bound to a DPoP key and it cannot be used as a bearer token")]
+    BoundTokenAsBearer,
+    #[error("The DPoP proof key does not match the key the token is bound to")]
+    KeyMismatch,
+}
+
+impl From<jsonwebtoken::errors::Error> for DpopError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        DpopError::InvalidProof(e.to_string())
+    }
+}
+
+/// Whether DPoP-bound tokens are accepted
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DpopMode {
+    /// Only bearer tokens are accepted (the `cnf` claim is ignored)
+    #[default]
+    Disabled,
+    /// Both bearer and DPoP-bound tokens are accepted
+    Allowed,
+    /// Only DPoP-bound tokens are accepted
+    Required,
+}
+
+impl FromStr for DpopMode {
+    type Err = DpopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "disabled" => Ok(DpopMode::Disabled),
+            "allowed" => Ok(DpopMode::Allowed),
+            "required" => Ok(DpopMode::Required),
+            _ => Err(DpopError::InvalidMode(s.to_string())),
+        }
+    }
+}
+
+/// Computes the JWK SHA-256 thumbprint (RFC 7638) of a public key
+fn jwk_thumbprint(jwk: &Value) -> Result<String, DpopError> {
+    let required_members = match jwk["kty"].as_str() {
+        Some("RSA") => json!({"e": jwk["e"], "kty": "RSA", "n": jwk["n"]}),
+        Some("EC") => json!({"crv": jwk["crv"], "kty": "EC", "x": jwk["x"], "y": jwk["y"]}),
+        Some("OKP") => json!({"crv": jwk["crv"], "kty": "OKP", "x": jwk["x"]}),
+        _ => return Err(DpopError::InvalidProof("unsupported jwk".to_string())),
+    };
+    // serde_json serializes object keys in lexicographic order and without whitespace
+    let hash = Sha256::digest(required_members.to_string().as_bytes());
+    Ok(URL_SAFE_NO_PAD.encode(hash))
+}
+
+/// Compares two URLs ignoring the query string and the fragment (RFC 9449, section 4.3)
+fn same_url(htu: &str, url: &str) -> bool {
+    let normalize = |url: &str| {
+        Url::parse(url).ok().map(|mut url| {
+            url.set_query(None);
+            url.set_fragment(None);
+            url
+        })
+    };
+    normalize(htu).is_some_and(|htu| Some(htu) == normalize(url))
+}
+
+#[derive(Debug, Default)]
+struct ReplayCacheEntries {
+    seen: HashSet<String>,
+    /// The `jti` of the proofs (with their expiration) in insertion order
+    queue: VecDeque<(String, i64)>,
+}
+
+/// A bounded, in-memory cache of the `jti` of the recently used proofs.
+/// Every Lambda instance has its own cache, so a proof can still be replayed against another instance.
+#[derive(Debug)]
+struct ReplayCache {
+    capacity: usize,
+    entries: Mutex<ReplayCacheEntries>,
+}
+
+impl ReplayCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Default::default(),
+        }
+    }
+
+    /// Records the given `jti` until it expires. Fails if it has already been used or if the cache
+    /// is full: valid entries are never evicted, since their proofs could be replayed.
+    fn insert(&self, jti: &str, expires_at: i64, now: i64) -> Result<(), DpopError> {
+        let mut entries = self.entries.lock().unwrap();
+        let ReplayCacheEntries { seen, queue } = &mut *entries;
+        if seen.contains(jti) {
+            return Err(DpopError::Replayed(jti.to_string()));
+        }
+
+        if queue.len() >= self.capacity {
+            // proofs can have different expirations, so the whole queue is checked
+            queue.retain(|(jti, exp)| {
+                let valid = *exp > now;
+                if !valid {
+                    seen.remove(jti);
+                }
+                valid
+            });
+        }
+        if queue.len() >= self.capacity {
+            tracing::warn!(
+                "The DPoP replay cache is full ({} entries): rejecting new proofs until some expire",
+                self.capacity
+            );
+            return Err(DpopError::ReplayCacheFull);
+        }
+
+        seen.insert(jti.to_string());
+        queue.push_back((jti.to_string(), expires_at));
+        Ok(())
+    }
+}
+
+/// Validates DPoP proofs (RFC 9449) and the binding between the proof key and the token
+#[derive(Debug, Default)]
+pub struct Dpop {
+    mode: DpopMode,
+    max_age: i64,
+    replay_cache: Option<ReplayCache>,
+}
+
+impl Dpop {
+    /// Creates a new validator. Proofs are accepted if their `iat` is within `max_age` from now.
+    /// Replay protection is disabled if `replay_cache_size` is 0.
+    pub fn new(mode: DpopMode, max_age: Duration, replay_cache_size: usize) -> Self {
+        Self {
+            mode,
+            max_age: max_age.num_seconds(),
+            replay_cache: (replay_cache_size > 0).then(|| ReplayCache::new(replay_cache_size)),
+        }
+    }
+
+    /// Returns `true` if DPoP proofs are validated. Proofs are bound to the method and URL of
+    /// the request (and are single use), so the decision depends on the request.
+    pub fn is_enabled(&self) -> bool {
+        self.mode != DpopMode::Disabled
+    }
+
+    /// Checks if the given authorization scheme is accepted
+    pub fn check_scheme(&self, scheme: TokenScheme) -> Result<(), DpopError> {
+        match (self.mode, scheme) {
+            (DpopMode::Disabled, TokenScheme::DPoP) | (DpopMode::Required, TokenScheme::Bearer) => {
+                Err(DpopError::UnsupportedScheme(scheme))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates the DPoP proof (if the token uses the DPoP scheme) and makes sure that
+    /// DPoP-bound tokens (with a `cnf.jkt` claim) are not used as bearer tokens.
+    pub fn validate(
+        &self,
+        scheme: TokenScheme,
+        access_token: &str,
+        claims: &Value,
+        proofs: &[&str],
+        method: &str,
+        url: Option<&str>,
+    ) -> Result<(), DpopError> {
+        let jkt = claims["cnf"]["jkt"].as_str();
+        match (self.mode, scheme) {
+            (DpopMode::Disabled, _) => Ok(()),
+            (_, TokenScheme::Bearer) => match jkt {
+                Some(_) => Err(DpopError::BoundTokenAsBearer),
+                None => Ok(()),
+            },
+            (_, TokenScheme::DPoP) => {
+                let proof = match proofs {
+                    [proof] => *proof,
+                    [] => return Err(DpopError::MissingProof),
+                    _ => return Err(DpopError::MultipleProofs),
+                };
+                let url = url.ok_or(DpopError::InvalidProof(
+                    "the request URL is not available (only REQUEST authorizers are supported)"
+                        .to_string(),
+                ))?;
+                let now = Utc::now().timestamp();
+                let (thumbprint, jti, iat) =
+                    self.verify_proof(proof, access_token, method, url, now)?;
+
+                match jkt {
+                    None => return Err(DpopError::UnboundToken),
+                    Some(jkt) if jkt != thumbprint => return Err(DpopError::KeyMismatch),
+                    _ => {}
+                }
+                if let Some(replay_cache) = &self.replay_cache {
+                    replay_cache.insert(&jti, iat + self.max_age, now)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Verifies a proof, returning the thumbprint of its key, its `jti` and its `iat`
+    fn verify_proof(
+        &self,
+        proof: &str,
+        access_token: &str,
+        method: &str,
+        url: &str,
+        now: i64,
+    ) -> Result<(String, String, i64), DpopError> {
+        let invalid = |message: &str| DpopError::InvalidProof(message.to_string());
+
+        let header = decode_header(proof)?;
+        if header.typ.as_deref() != Some("dpop+jwt") {
+            return Err(invalid("typ must be dpop+jwt"));
+        }
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(invalid("symmetric algorithms are not allowed"));
+        }
+        let jwk = header.jwk.as_ref().ok_or(invalid("missing jwk"))?;
+        // the parsed jwk drops the private members, so they are checked on the raw header
+        let raw_header: Value = proof
+            .split('.')
+            .next()
+            .and_then(|header| URL_SAFE_NO_PAD.decode(header).ok())
+            .and_then(|header| serde_json::from_slice(&header).ok())
+            .ok_or(invalid("invalid header"))?;
+        let raw_jwk = &raw_header["jwk"];
+        if raw_jwk.get("d").is_some() {
+            return Err(invalid("the jwk must not contain a private key"));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        let claims = decode::<Value>(proof, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+
+        if claims["htm"].as_str() != Some(method) {
+            return Err(invalid("htm does not match the request method"));
+        }
+        if !claims["htu"].as_str().is_some_and(|htu| same_url(htu, url)) {
+            return Err(invalid("htu does not match the request URL"));
+        }
+        let iat = claims["iat"].as_i64().ok_or(invalid("missing iat"))?;
+        if (now - iat).abs() > self.max_age {
+            return Err(invalid("iat is too far from the current time"));
+        }
+        let ath = URL_SAFE_NO_PAD.encode(Sha256::digest(access_token.as_bytes()));
+        if claims["ath"].as_str() != Some(&ath) {
+            return Err(invalid("ath does not match the access token"));
+        }
+        let jti = claims["jti"].as_str().ok_or(invalid("missing jti"))?;
+
+        Ok((jwk_thumbprint(raw_jwk)?, jti.to_string(), iat))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use tracing_test::traced_test;
+
+    /// The public key of `tests/fixtures/keys/es256/private.pem` as a JWK (without `kid`, `alg` and `use`)
+    pub(crate) fn es256_jwk() -> Value {
+        let mut jwk: Value =
+            serde_json::from_str(include_str!("../tests/fixtures/keys/es256/jwk.json")).unwrap();
+        let jwk_object = jwk.as_object_mut().unwrap();
+        for member in ["kid", "alg", "use"] {
+            jwk_object.remove(member);
+        }
+        jwk
+    }
+
+    /// Creates a proof signed with `tests/fixtures/keys/es256/private.pem`
+    pub(crate) fn make_proof(claims: Value) -> String {
+        let header: Header = serde_json::from_value(
+            json!({ "typ": "dpop+jwt", "alg": "ES256", "jwk": es256_jwk() }),
+        )
+        .unwrap();
+        jsonwebtoken::encode(
+            &header,
+            &claims,
+            &EncodingKey::from_ec_pem(include_bytes!("../tests/fixtures/keys/es256/private.pem"))
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    pub(crate) fn es256_jkt() -> String {
+        jwk_thumbprint(&es256_jwk()).unwrap()
+    }
+
+    pub(crate) fn ath(access_token: &str) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(access_token.as_bytes()))
+    }
+
+    fn make_dpop() -> Dpop {
+        Dpop::new(DpopMode::Allowed, Duration::try_seconds(60).unwrap(), 10)
+    }
+
+    fn valid_claims() -> Value {
+        json!({
+            "jti": "proof-1",
+            "htm": "GET",
+            "htu": "https://api.example.com/pets",
+            "iat": Utc::now().timestamp(),
+            "ath": ath("access-token"),
+        })
+    }
+
+    #[test]
+    fn it_should_parse_the_dpop_mode() {
+        assert_eq!("".parse::<DpopMode>().unwrap(), DpopMode::Disabled);
+        assert_eq!("Required".parse::<DpopMode>().unwrap(), DpopMode::Required);
+        assert!("optional".parse::<DpopMode>().is_err());
+    }
+
+    #[test]
+    fn it_should_compute_jwk_thumbprints() {
+        // example from RFC 7638, section 3.1
+        let jwk = json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29"
+        });
+        assert_eq!(
+            jwk_thumbprint(&jwk).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn it_should_check_the_authorization_scheme() {
+        assert!(Dpop::default().check_scheme(TokenScheme::Bearer).is_ok());
+        assert!(Dpop::default().check_scheme(TokenScheme::DPoP).is_err());
+        let dpop = Dpop::new(DpopMode::Required, Duration::zero(), 0);
+        assert!(dpop.check_scheme(TokenScheme::Bearer).is_err());
+        assert!(dpop.check_scheme(TokenScheme::DPoP).is_ok());
+    }
+
+    #[test]
+    fn it_should_validate_a_proof_bound_to_the_token() {
+        let dpop = make_dpop();
+        let claims = json!({ "cnf": { "jkt": es256_jkt() } });
+        let proof = make_proof(valid_claims());
+
+        let validate = |claims: &Value, proofs: &[&str]| {
+            dpop.validate(
+                TokenScheme::DPoP,
+                "access-token",
+                claims,
+                proofs,
+                "GET",
+                Some("https://api.example.com/pets"),
+            )
+        };
+        assert!(validate(&claims, &[&proof]).is_ok());
+        assert!(matches!(
+            validate(&claims, &[&proof]),
+            Err(DpopError::Replayed(_))
+        ));
+        assert!(matches!(
+            validate(&claims, &[]),
+            Err(DpopError::MissingProof)
+        ));
+        assert!(matches!(
+            validate(&claims, &[&proof, &proof]),
+            Err(DpopError::MultipleProofs)
+        ));
+        assert!(matches!(
+            validate(&json!({}), &[&proof]),
+            Err(DpopError::UnboundToken)
+        ));
+        assert!(matches!(
+            validate(&json!({ "cnf": { "jkt": "another-key" } }), &[&proof]),
+            Err(DpopError::KeyMismatch)
+        ));
+        assert!(matches!(
+            dpop.validate(
+                TokenScheme::Bearer,
+                "access-token",
+                &claims,
+                &[],
+                "GET",
+                None
+            ),
+            Err(DpopError::BoundTokenAsBearer)
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_proofs() {
+        let dpop = make_dpop();
+        let now = Utc::now().timestamp();
+        let cases = [
+            ("htm", json!("POST")),
+            ("htu", json!("https://api.example.com/users")),
+            ("iat", json!(now - 120)),
+            ("ath", json!(ath("another-access-token"))),
+            ("jti", json!(null)),
+        ];
+
+        for (claim, value) in cases {
+            let mut claims = valid_claims();
+            claims[claim] = value;
+            assert!(
+                matches!(
+                    dpop.verify_proof(
+                        &make_proof(claims),
+                        "access-token",
+                        "GET",
+                        "https://api.example.com/pets",
+                        now
+                    ),
+                    Err(DpopError::InvalidProof(_))
+                ),
+                "the proof with an invalid {} should be rejected",
+                claim
+            );
+        }
+
+        // the query string is ignored
+        assert!(dpop
+            .verify_proof(
+                &make_proof(valid_claims()),
+                "access-token",
+                "GET",
+                "https://API.example.com:443/pets?page=2",
+                now
+            )
+            .is_ok());
+
+        // the proof must be a DPoP JWT
+        let header: Header = serde_json::from_value(json!({ "alg": "HS256" })).unwrap();
+        let proof = jsonwebtoken::encode(
+            &header,
+            &valid_claims(),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(dpop
+            .verify_proof(
+                &proof,
+                "access-token",
+                "GET",
+                "https://api.example.com/pets",
+                now
+            )
+            .is_err());
+    }
+
+    #[test]
+    #[traced_test]
+    fn it_should_reject_new_proofs_if_the_replay_cache_is_full() {
+        let replay_cache = ReplayCache::new(2);
+        assert!(replay_cache.insert("jti-1", 100, 0).is_ok());
+        assert!(matches!(
+            replay_cache.insert("jti-1", 100, 0),
+            Err(DpopError::Replayed(_))
+        ));
+        assert!(replay_cache.insert("jti-2", 200, 0).is_ok());
+        // valid entries are never evicted
+        assert!(matches!(
+            replay_cache.insert("jti-3", 100, 0),
+            Err(DpopError::ReplayCacheFull)
+        ));
+        assert!(logs_contain("The DPoP replay cache is full"));
+        assert!(matches!(
+            replay_cache.insert("jti-1", 100, 50),
+            Err(DpopError::Replayed(_))
+        ));
+
+        // expired entries are evicted
+        assert!(replay_cache.insert("jti-3", 200, 150).is_ok());
+        assert!(replay_cache.insert("jti-1", 200, 150).is_err());
+        assert!(matches!(
+            replay_cache.insert("jti-2", 200, 150),
+            Err(DpopError::Replayed(_))
+        ));
+    }
+}
@@ -0,0 +1,256 @@
+/// The characters that wildcards and placeholders never match in issuer and audience patterns,
+/// so that they cannot span several URL components (e.g. the host and the path)
+const URL_SEPARATORS: &[char] = &['/', '.', '?', '#', '@', ':'];
+
+/// Matches `value` against a glob `pattern` where `*` matches any sequence of characters
+/// (including none) and `?` matches exactly one character.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    glob_match_within(pattern, value, &[])
+}
+
+/// Same as [`glob_match`], but `*` and `?` never match any of the given `separators`
+fn glob_match_within(pattern: &str, value: &str, separators: &[char]) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    // position of the last `*` seen in the pattern and the value position it was matched at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == value[v] || (*c == '?' && !separators.contains(&value[v])) => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` consume one more character and retry
+                Some((star_p, star_v)) if !separators.contains(&value[star_v]) => {
+                    backtrack = Some((star_p, star_v + 1));
+                    p = star_p + 1;
+                    v = star_v + 1;
+                }
+                _ => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Matches an issuer or audience `value` against a glob `pattern` that can also contain the given
+/// `placeholder` (e.g. `{tenant}`), matching a non-empty sequence of letters, digits, `-` and `_`.
+/// Unlike [`glob_match`], `*` and `?` never match `/ . ? # @ :`, so they cannot span URL components.
+/// Returns `None` if the value doesn't match, or the captured value (if any) otherwise.
+pub fn glob_capture<'a>(
+    pattern: &str,
+    value: &'a str,
+    placeholder: &str,
+) -> Option<Option<&'a str>> {
+    let Some((prefix, suffix)) = pattern.split_once(placeholder) else {
+        return glob_match_within(pattern, value, URL_SEPARATORS).then_some(None);
+    };
+
+    let is_placeholder_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let boundaries = || {
+        value
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(value.len()))
+    };
+    for start in
+        boundaries().filter(|start| glob_match_within(prefix, &value[..*start], URL_SEPARATORS))
+    {
+        for end in boundaries().filter(|end| *end > start) {
+            let segment = &value[start..end];
+            if !segment.chars().all(is_placeholder_char) {
+                break;
+            }
+            if glob_match_within(suffix, &value[end..], URL_SEPARATORS) {
+                return Some(Some(segment));
+            }
+        }
+    }
+    None
+}
+
+/// Matches a request against a route in the form `"<METHOD> <path>"` (e.g. `"GET /admin/*"`)
+/// or just `"<path>"` to match any method. Both the method and the path can be glob patterns.
+pub fn route_match(route: &str, method: &str, path: &str) -> bool {
+    match route.trim().split_once(' ') {
+        Some((route_method, route_path)) => {
+            glob_match(&route_method.to_uppercase(), &method.to_uppercase())
+                && glob_match(route_path.trim(), path)
+        }
+        None => glob_match(route.trim(), path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_match_literal_patterns() {
+        assert!(glob_match("/admin", "/admin"));
+        assert!(!glob_match("/admin", "/admin/users"));
+        assert!(!glob_match("/admin", "/other"));
+    }
+
+    #[test]
+    fn it_should_match_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("/admin/*", "/admin/users/42"));
+        assert!(glob_match("*.example.com", "api.example.com"));
+        assert!(!glob_match("*.example.com", "example.org"));
+        // `*` can span several path segments
+        assert!(glob_match("/api/*/v2", "/api/tenants/42/v2"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn it_should_match_single_character_wildcards() {
+        assert!(glob_match("v?", "v1"));
+        assert!(!glob_match("v?", "v"));
+        assert!(!glob_match("v?", "v12"));
+    }
+
+    #[test]
+    fn it_should_capture_placeholders() {
+        let pattern = "https://login.microsoftonline.com/{tenant}/v2.0";
+        assert_eq!(
+            glob_capture(
+                pattern,
+                "https://login.microsoftonline.com/1234-abcd/v2.0",
+                "{tenant}"
+            ),
+            Some(Some("1234-abcd"))
+        );
+        assert_eq!(
+            glob_capture(
+                pattern,
+                "https://login.microsoftonline.com//v2.0",
+                "{tenant}"
+            ),
+            None
+        );
+        assert_eq!(
+            glob_capture(
+                pattern,
+                "https://login.microsoftonline.com/a/b/v2.0",
+                "{tenant}"
+            ),
+            None
+        );
+        assert_eq!(
+            glob_capture(
+                "https://{tenant}.auth0.com/",
+                "https://acme.auth0.com/",
+                "{tenant}"
+            ),
+            Some(Some("acme"))
+        );
+        assert_eq!(
+            glob_capture(
+                "https://{tenant}.auth0.com/",
+                "https://my_tenant-1.auth0.com/",
+                "{tenant}"
+            ),
+            Some(Some("my_tenant-1"))
+        );
+        // patterns without placeholder
+        assert_eq!(
+            glob_capture(
+                "https://*.example.com",
+                "https://idp.example.com",
+                "{tenant}"
+            ),
+            Some(None)
+        );
+        assert_eq!(
+            glob_capture("https://*.example.com", "https://example.org", "{tenant}"),
+            None
+        );
+    }
+
+    #[test]
+    fn it_should_not_capture_url_separators() {
+        for (pattern, value) in [
+            (
+                "https://{tenant}.auth0.com/",
+                "https://evil.com#.auth0.com/",
+            ),
+            (
+                "https://{tenant}.auth0.com/",
+                "https://evil.com/x.auth0.com/",
+            ),
+            ("https://{tenant}.auth0.com/", "https://a.b.auth0.com/"),
+            (
+                "https://{tenant}.auth0.com/",
+                "https://evil.com?.auth0.com/",
+            ),
+            (
+                "https://{tenant}.auth0.com/",
+                "https://user@evil.auth0.com/",
+            ),
+            (
+                "https://{tenant}.auth0.com/",
+                "https://evil.com:443.auth0.com/",
+            ),
+            (
+                "https://login.microsoftonline.com/{tenant}/v2.0",
+                "https://login.microsoftonline.com/a%2Fb/v2.0",
+            ),
+        ] {
+            assert_eq!(glob_capture(pattern, value, "{tenant}"), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn it_should_not_match_url_separators_with_wildcards() {
+        for (pattern, value) in [
+            ("https://*.auth0.com/", "https://evil.com/x.auth0.com/"),
+            ("https://*.auth0.com/", "https://evil.com#.auth0.com/"),
+            ("https://*.auth0.com/", "https://evil.com?.auth0.com/"),
+            ("https://*.auth0.com/", "https://a.evil.auth0.com/"),
+            ("https://*.auth0.com/", "https://evil.com@x.auth0.com/"),
+            ("https://*.auth0.com/", "https://evil.com:1.auth0.com/"),
+            ("https://idp?.example.com", "https://idp/.example.com"),
+            ("https://*/v2.0", "https://login.example.com/tenant/v2.0"),
+        ] {
+            assert_eq!(glob_capture(pattern, value, "{tenant}"), None, "{}", value);
+        }
+        assert_eq!(
+            glob_capture(
+                "https://*.auth0.com/",
+                "https://acme.auth0.com/",
+                "{tenant}"
+            ),
+            Some(None)
+        );
+        assert_eq!(
+            glob_capture(
+                "https://idp?.example.com",
+                "https://idp1.example.com",
+                "{tenant}"
+            ),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn it_should_match_routes() {
+        assert!(route_match("GET /admin/*", "GET", "/admin/users"));
+        assert!(route_match("get /admin/*", "GET", "/admin/users"));
+        assert!(!route_match("POST /admin/*", "GET", "/admin/users"));
+        assert!(route_match("* /admin/*", "DELETE", "/admin/users"));
+        assert!(route_match("/admin/*", "PUT", "/admin/users"));
+        assert!(!route_match("/admin/*", "PUT", "/users"));
+    }
+}
@@ -1,16 +1,37 @@
 use crate::{
     accepted_algorithms::AcceptedAlgorithms,
     accepted_claims::AcceptedClaims,
-    cel_validation::CelValidator,
+    accepted_client_ids::AcceptedClientIds,
+    audit_log::{AuditEvent, AuditLog},
+    cel_validation::{CelValidationError, CelValidator},
+    certificate_binding::CertificateBinding,
+    context_enrichment::ContextEnrichment,
+    decision::{Decision, DenyReason},
+    denylist::Denylist,
+    dpop::Dpop,
+    introspection::{Introspection, IntrospectionMode},
+    jwe::{is_jwe, JweDecryptor},
     keys_storage::KeysStorage,
-    models::{TokenAuthorizerEvent, TokenAuthorizerResponse},
+    log_redaction::{token_fingerprint, LogRedactor},
+    metrics::Metrics,
+    models::{RequestMetadata, TokenAuthorizerEvent, TokenAuthorizerResponse},
     parse_token_from_header::parse_token_from_header,
     principalid_claims::PrincipalIDClaims,
+    rbac::Rbac,
+    response_size::ResponseSizeGuard,
+    telemetry::Telemetry,
+    usage_identifier::UsageIdentifier,
 };
 use futures_util::future::{BoxFuture, FutureExt};
-use jsonwebtoken::{decode, decode_header, Validation};
-use lambda_runtime::{Error, LambdaEvent, Service};
-use std::task::{Context, Poll};
+use jsonwebtoken::{decode, decode_header, Header, Validation};
+use lambda_runtime::{Context as LambdaContext, Error, LambdaEvent, Service};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    task::{Context, Poll},
+    time::Instant,
+};
+use tracing::Instrument;
 
 pub struct Handler {
     pub keys: &'static KeysStorage,
@@ -19,6 +40,20 @@ pub struct Handler {
     pub accepted_audiences: &'static AcceptedClaims,
     pub accepted_signing_algorithms: &'static AcceptedAlgorithms,
     pub cel_validator: &'static CelValidator,
+    pub context_enrichment: &'static ContextEnrichment,
+    pub metrics: &'static Metrics,
+    pub log_redactor: &'static LogRedactor,
+    pub audit_log: &'static AuditLog,
+    pub telemetry: &'static Telemetry,
+    pub denylist: &'static Denylist,
+    pub introspection: &'static Introspection,
+    pub jwe_decryptor: &'static JweDecryptor,
+    pub dpop: &'static Dpop,
+    pub certificate_binding: &'static CertificateBinding,
+    pub response_size_guard: &'static ResponseSizeGuard,
+    pub usage_identifier: &'static UsageIdentifier,
+    pub accepted_client_ids: &'static AcceptedClientIds,
+    pub rbac: &'static Rbac,
 }
 
 impl Handler {
@@ -37,88 +72,487 @@ impl Handler {
             accepted_audiences,
             accepted_signing_algorithms,
             cel_validator,
+            context_enrichment: Box::leak(Box::default()),
+            metrics: Box::leak(Box::default()),
+            log_redactor: Box::leak(Box::default()),
+            audit_log: Box::leak(Box::default()),
+            telemetry: Box::leak(Box::default()),
+            denylist: Box::leak(Box::default()),
+            introspection: Box::leak(Box::default()),
+            jwe_decryptor: Box::leak(Box::default()),
+            dpop: Box::leak(Box::default()),
+            certificate_binding: Box::leak(Box::default()),
+            response_size_guard: Box::leak(Box::default()),
+            usage_identifier: Box::leak(Box::default()),
+            accepted_client_ids: Box::leak(Box::default()),
+            rbac: Box::leak(Box::default()),
+        }
+    }
+
+    pub fn with_context_enrichment(
+        mut self,
+        context_enrichment: &'static ContextEnrichment,
+    ) -> Self {
+        self.context_enrichment = context_enrichment;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: &'static Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_log_redactor(mut self, log_redactor: &'static LogRedactor) -> Self {
+        self.log_redactor = log_redactor;
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit_log: &'static AuditLog) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    pub fn with_telemetry(mut self, telemetry: &'static Telemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    pub fn with_denylist(mut self, denylist: &'static Denylist) -> Self {
+        self.denylist = denylist;
+        self
+    }
+
+    pub fn with_introspection(mut self, introspection: &'static Introspection) -> Self {
+        self.introspection = introspection;
+        self
+    }
+
+    pub fn with_jwe_decryptor(mut self, jwe_decryptor: &'static JweDecryptor) -> Self {
+        self.jwe_decryptor = jwe_decryptor;
+        self
+    }
+
+    pub fn with_dpop(mut self, dpop: &'static Dpop) -> Self {
+        self.dpop = dpop;
+        self
+    }
+
+    pub fn with_certificate_binding(
+        mut self,
+        certificate_binding: &'static CertificateBinding,
+    ) -> Self {
+        self.certificate_binding = certificate_binding;
+        self
+    }
+
+    pub fn with_response_size_guard(
+        mut self,
+        response_size_guard: &'static ResponseSizeGuard,
+    ) -> Self {
+        self.response_size_guard = response_size_guard;
+        self
+    }
+
+    pub fn with_usage_identifier(mut self, usage_identifier: &'static UsageIdentifier) -> Self {
+        self.usage_identifier = usage_identifier;
+        self
+    }
+
+    pub fn with_accepted_client_ids(
+        mut self,
+        accepted_client_ids: &'static AcceptedClientIds,
+    ) -> Self {
+        self.accepted_client_ids = accepted_client_ids;
+        self
+    }
+
+    pub fn with_rbac(mut self, rbac: &'static Rbac) -> Self {
+        self.rbac = rbac;
+        self
+    }
+
+    /// Returns `true` if the decision depends on the request (e.g. on its path), so the
+    /// allow policy must be scoped to the method ARN rather than cached for every route
+    fn is_request_dependent(&self) -> bool {
+        self.cel_validator.is_request_dependent()
+            || self.principal_id_claims.is_request_dependent()
+            || self.context_enrichment.is_request_dependent()
+            || self.rbac.has_routes()
+            || self.dpop.is_enabled()
+            || self.certificate_binding.is_enabled()
+    }
+
+    async fn do_call(
+        self,
+        event: TokenAuthorizerEvent,
+        context: LambdaContext,
+    ) -> Result<TokenAuthorizerResponse, Error> {
+        let start = Instant::now();
+        let request_metadata = event.request_metadata();
+        let mut decision = Decision {
+            stage: request_metadata.stage.clone(),
+            xray_trace_id: context.xray_trace_id.clone(),
+            ..Default::default()
+        };
+        let span = tracing::info_span!(
+            "authorizer.authorize",
+            faas.invocation_id = context.request_id,
+            aws.xray.trace_id = context.xray_trace_id,
+            authorizer.decision = tracing::field::Empty,
+            authorizer.deny_reason = tracing::field::Empty,
+        );
+        Telemetry::set_xray_parent(&span, context.xray_trace_id.as_deref());
+
+        let response = match self
+            .authorize(&event, &request_metadata, &mut decision)
+            .instrument(span.clone())
+            .await
+        {
+            Ok(response) => response,
+            Err(reason) => {
+                decision.deny_reason = Some(reason);
+                TokenAuthorizerResponse::deny(&event.method_arn)
+            }
+        };
+
+        decision.latency = start.elapsed();
+        decision.jwks = self.keys.take_stats();
+        span.record("authorizer.decision", decision.effect());
+        if let Some(reason) = &decision.deny_reason {
+            span.record("authorizer.deny_reason", reason.name());
         }
+        span.in_scope(|| decision.log());
+        self.metrics.emit(&decision);
+        self.audit_log.emit(&AuditEvent::new(
+            &decision,
+            &context.request_id,
+            &event.method_arn,
+            &response,
+        ));
+        drop(span);
+        self.telemetry.flush();
+
+        Ok(response)
     }
 
-    async fn do_call(self, event: TokenAuthorizerEvent) -> Result<TokenAuthorizerResponse, Error> {
-        // TODO: custom metrics using EMF logs
+    /// Validates the token in the given event. On failure, returns the reason why the request is denied.
+    async fn authorize(
+        &self,
+        event: &TokenAuthorizerEvent,
+        request_metadata: &RequestMetadata,
+        decision: &mut Decision,
+    ) -> Result<TokenAuthorizerResponse, DenyReason> {
         // extract token from header
-        let token = match parse_token_from_header(&event.authorization_token) {
-            Ok(token) => token,
+        let authorization_header = event.authorization_header();
+        if authorization_header.trim().is_empty() {
+            tracing::debug!("Missing authorization header");
+            return Err(DenyReason::MissingHeader);
+        }
+        let (scheme, access_token) = match parse_token_from_header(authorization_header) {
+            Ok(scheme_and_token) => scheme_and_token,
             Err(e) => {
-                tracing::info!(
+                tracing::debug!(
                     "Failed to extract token from header (header_value='{}'): {}",
-                    event.authorization_token,
+                    self.log_redactor.authorization_header(authorization_header),
                     e
                 );
-                return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
+                return Err(DenyReason::MalformedScheme);
+            }
+        };
+
+        if let Err(e) = self.dpop.check_scheme(scheme) {
+            tracing::debug!("{}", e);
+            return Err(DenyReason::from(&e));
+        }
+
+        decision.token_fingerprint = Some(token_fingerprint(access_token));
+
+        // the introspection endpoint receives the token as issued (before any decryption)
+        let (token_header, claims) = match self.introspection.mode() {
+            Some(IntrospectionMode::Always) => (None, self.introspect(access_token).await?),
+            mode => {
+                // encrypted tokens (JWE) contain a signed token (nested JWT) that is validated as usual
+                let decrypted_token;
+                let token = if self.jwe_decryptor.is_enabled() && is_jwe(access_token) {
+                    decrypted_token = match tracing::info_span!("token.decrypt")
+                        .in_scope(|| self.jwe_decryptor.decrypt(access_token))
+                    {
+                        Ok(decrypted_token) => decrypted_token,
+                        Err(e) => {
+                            tracing::debug!(
+                                "Failed to decrypt token (token='{}'): {}",
+                                self.log_redactor.token(access_token),
+                                e
+                            );
+                            return Err(DenyReason::UndecryptableToken);
+                        }
+                    };
+                    decrypted_token.as_str()
+                } else {
+                    access_token
+                };
+
+                match tracing::info_span!("token.decode_header").in_scope(|| decode_header(token)) {
+                    Ok(token_header) => {
+                        let (token_header, claims) =
+                            self.verify_jwt(token, token_header, decision).await?;
+                        (Some(token_header), claims)
+                    }
+                    // not a JWT: it might be an opaque token
+                    Err(_) if mode.is_some() => (None, self.introspect(access_token).await?),
+                    Err(e) => {
+                        tracing::debug!(
+                            "Failed to parse token header (token='{}'): {}",
+                            self.log_redactor.token(token),
+                            e
+                        );
+                        return Err(DenyReason::UndecodableHeader);
+                    }
+                }
+            }
+        };
+
+        // the tenant captured by the issuer (or audience) patterns is exposed to CEL
+        let (Some(issuer_match), Some(audience_match)) = (
+            self.accepted_issuers.find_match(&claims["iss"]),
+            self.accepted_audiences.find_match(&claims["aud"]),
+        ) else {
+            let (accepted_claims, reason) = match self.accepted_issuers.accepts(&claims["iss"]) {
+                false => (self.accepted_issuers, DenyReason::BadIssuer),
+                true => (self.accepted_audiences, DenyReason::BadAudience),
+            };
+            tracing::debug!(
+                "Token has an invalid '{}' claim (value='{}')",
+                accepted_claims.claim_name(),
+                claims[accepted_claims.claim_name()]
+            );
+            return Err(reason);
+        };
+        if let Err(e) = self.accepted_client_ids.validate(&claims) {
+            tracing::debug!("Client ID validation failed: {}", e);
+            return Err(DenyReason::BadClientId);
+        }
+        let tenant = issuer_match.tenant.or(audience_match.tenant);
+        // the normalized roles are also exposed to CEL
+        let roles = self.rbac.roles(&claims);
+        let request_metadata = &match (&tenant, roles.is_empty()) {
+            (None, true) => Cow::Borrowed(request_metadata),
+            _ => Cow::Owned(RequestMetadata {
+                tenant: tenant.clone().unwrap_or_default(),
+                roles: roles.clone(),
+                ..request_metadata.clone()
+            }),
+        };
+
+        // proof-of-possession (if the token is DPoP-bound)
+        if let Err(e) = tracing::info_span!("dpop.verify").in_scope(|| {
+            self.dpop.validate(
+                scheme,
+                access_token,
+                &claims,
+                &event.header_values("dpop"),
+                &request_metadata.method,
+                event.request_url().as_deref(),
+            )
+        }) {
+            tracing::debug!("DPoP validation failed: {}", e);
+            return Err(DenyReason::from(&e));
+        }
+
+        // proof-of-possession (if the token is bound to a client certificate)
+        if let Err(e) = tracing::info_span!("mtls.verify").in_scope(|| {
+            self.certificate_binding
+                .validate(&claims, event.client_cert_pem())
+        }) {
+            tracing::debug!("Certificate binding validation failed: {}", e);
+            return Err(DenyReason::from(&e));
+        }
+
+        let claim = |name: &str| claims[name].as_str().map(str::to_string);
+        decision.issuer = claim("iss");
+        decision.subject = claim("sub");
+        decision.client_id = claim("client_id").or_else(|| claim("azp"));
+        decision.jti = claim("jti");
+
+        // revocation check (if configured)
+        match self.denylist.find_match(&claims).await {
+            Ok(Some(claim)) => {
+                tracing::debug!("Token has been revoked (matching claim: {})", claim);
+                return Err(DenyReason::Revoked { claim });
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::debug!("Unable to check the denylist: {}", e);
+                return Err(DenyReason::DenylistUnavailable);
+            }
+        }
+
+        // role based access control (if configured)
+        if let Err(e) = self
+            .rbac
+            .check(&roles, &request_metadata.method, &request_metadata.path)
+        {
+            tracing::debug!("RBAC check failed (roles='{}'): {}", roles.join(", "), e);
+            return Err(DenyReason::from(&e));
+        }
+
+        // CEL validation (if configured)
+        if let Err(e) =
+            self.cel_validator
+                .validate(token_header.as_ref(), &claims, request_metadata)
+        {
+            match e {
+                CelValidationError::RuleFailed { .. } => tracing::debug!(
+                    "CEL validation failed (claims='{}'): {}",
+                    self.log_redactor.claims(&claims),
+                    e
+                ),
+                _ => tracing::debug!(
+                    "CEL validation failed (expression='{}', claims='{}'): {}",
+                    self.cel_validator.expression(),
+                    self.log_redactor.claims(&claims),
+                    e
+                ),
+            }
+            return Err(DenyReason::from(&e));
+        }
+
+        let principal_id = match self.principal_id_claims.get_principal_id(
+            token_header.as_ref(),
+            &claims,
+            request_metadata,
+        ) {
+            Ok(principal_id) => principal_id,
+            Err(e) => {
+                tracing::debug!("Failed to compute the principal ID: {}", e);
+                return Err(DenyReason::PrincipalIdError);
             }
         };
 
-        // parse token header
-        let token_header = match decode_header(token) {
-            Ok(token_header) => token_header,
+        let usage_identifier_key = match self.usage_identifier.get_usage_identifier_key(&claims) {
+            Ok(usage_identifier_key) => usage_identifier_key,
             Err(e) => {
-                tracing::info!("Failed to parse token header (token='{}'): {}", token, e);
-                return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
+                tracing::debug!("Failed to compute the usage identifier key: {}", e);
+                return Err(DenyReason::MissingUsageIdentifierKey);
             }
         };
 
+        let context =
+            match self
+                .context_enrichment
+                .build(token_header.as_ref(), &claims, request_metadata)
+            {
+                Ok(mut context) => {
+                    if let (Some(tenant_key), Some(tenant)) =
+                        (self.context_enrichment.tenant_key(), tenant)
+                    {
+                        context
+                            .entry(tenant_key.to_string())
+                            .or_insert(Value::String(tenant));
+                    }
+                    if !roles.is_empty() {
+                        context
+                            .entry("roles".to_string())
+                            .or_insert(Value::String(roles.join(",")));
+                    }
+                    context
+                }
+                Err(e) => {
+                    tracing::debug!("Failed to compute the authorizer context: {}", e);
+                    return Err(DenyReason::ContextError);
+                }
+            };
+
+        let mut response = TokenAuthorizerResponse::allow(&principal_id, &claims)
+            .with_usage_identifier_key(usage_identifier_key);
+        if self.is_request_dependent() {
+            response = response.for_resource(&event.method_arn);
+        }
+        let response = match self.context_enrichment.include_jwt_claims() {
+            true => response,
+            false => response.without_jwt_claims(),
+        };
+        self.response_size_guard
+            .enforce(response.with_context(context))
+            .map_err(|e| {
+                tracing::warn!("Failed to build the authorizer response: {}", e);
+                DenyReason::ResponseTooLarge
+            })
+    }
+
+    /// Verifies the signature and the standard claims of a JWT, returning its header and claims
+    async fn verify_jwt(
+        &self,
+        token: &str,
+        token_header: Header,
+        decision: &mut Decision,
+    ) -> Result<(Header, Value), DenyReason> {
+        decision.kid = token_header.kid.clone();
+        decision.alg = Some(token_header.alg);
+
         // validate the signing algorithm
         if let Err(e) = self.accepted_signing_algorithms.assert(&token_header.alg) {
-            tracing::info!(e);
-            return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
+            tracing::debug!(e);
+            return Err(DenyReason::AlgorithmNotAccepted);
         }
 
         let key = match &token_header.kid {
-            Some(key_id) => match self.keys.get(key_id).await {
+            Some(key_id) => match self
+                .keys
+                .get(key_id, decision.xray_trace_id.as_deref())
+                .await
+            {
                 Ok(key) => key,
                 Err(e) => {
-                    tracing::info!("Failed to retrieve key (key_id='{}'): {}", key_id, e);
-                    return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
+                    tracing::debug!("Failed to retrieve key (key_id='{}'): {}", key_id, e);
+                    return Err(DenyReason::from(&e));
                 }
             },
             None => {
-                tracing::info!(
+                tracing::debug!(
                     "Missing kid in token header (token_header='{:?}')",
                     token_header
                 );
-                return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
+                return Err(DenyReason::MissingKid);
             }
         };
 
+        // the issuer and the audience are checked after decoding (they can be patterns)
         let mut validation = Validation::new(token_header.alg);
-        validation.set_audience(&self.accepted_audiences.accepted_values());
-        validation.set_issuer(&self.accepted_issuers.accepted_values());
-        let token_payload = match decode::<serde_json::Value>(token, &key, &validation) {
+        validation.validate_aud = false;
+        let token_payload = match tracing::info_span!("token.verify", jwt.alg = ?token_header.alg)
+            .in_scope(|| decode::<serde_json::Value>(token, &key, &validation))
+        {
             Ok(token_payload) => token_payload,
             Err(e) => {
-                tracing::info!("Failed to validate token (token='{}'): {}", token, e);
-                return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
+                tracing::debug!(
+                    "Failed to validate token (token='{}', token_header='{:?}'): {}",
+                    self.log_redactor.token(token),
+                    token_header,
+                    e
+                );
+                return Err(DenyReason::from(&e));
             }
         };
 
-        // CEL validation (if configured)
-        if let Err(e) = self
-            .cel_validator
-            .validate(&token_header, &token_payload.claims)
-        {
-            tracing::info!(
-                "CEL validation failed (expression='{}'): {}",
-                self.cel_validator.expression(),
-                e
-            );
-            return Ok(TokenAuthorizerResponse::deny(&event.method_arn));
-        }
+        Ok((token_header, token_payload.claims))
+    }
 
-        let principal_id = self
-            .principal_id_claims
-            .get_principal_id_from_claims(&token_payload.claims);
+    /// Validates a token using the introspection endpoint, returning its claims
+    async fn introspect(&self, token: &str) -> Result<Value, DenyReason> {
+        let claims = match self.introspection.introspect(token).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                tracing::debug!(
+                    "Token introspection failed (token='{}'): {}",
+                    self.log_redactor.token(token),
+                    e
+                );
+                return Err(DenyReason::from(&e));
+            }
+        };
 
-        Ok(TokenAuthorizerResponse::allow(
-            &principal_id,
-            &token_payload.claims,
-        ))
+        Ok(claims)
     }
 }
 
@@ -131,6 +565,20 @@ impl Clone for Handler {
             accepted_audiences: self.accepted_audiences,
             accepted_signing_algorithms: self.accepted_signing_algorithms,
             cel_validator: self.cel_validator,
+            context_enrichment: self.context_enrichment,
+            metrics: self.metrics,
+            log_redactor: self.log_redactor,
+            audit_log: self.audit_log,
+            telemetry: self.telemetry,
+            denylist: self.denylist,
+            introspection: self.introspection,
+            jwe_decryptor: self.jwe_decryptor,
+            dpop: self.dpop,
+            certificate_binding: self.certificate_binding,
+            response_size_guard: self.response_size_guard,
+            usage_identifier: self.usage_identifier,
+            accepted_client_ids: self.accepted_client_ids,
+            rbac: self.rbac,
         }
     }
 }
@@ -145,17 +593,31 @@ impl Service<LambdaEvent<TokenAuthorizerEvent>> for Handler {
     }
 
     fn call(&mut self, req: LambdaEvent<TokenAuthorizerEvent>) -> Self::Future {
-        let (event, _) = req.into_parts();
-        self.clone().do_call(event).boxed()
+        let (event, context) = req.into_parts();
+        self.clone().do_call(event, context).boxed()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        accepted_client_ids::DEFAULT_CLIENT_ID_CLAIMS,
+        certificate_binding::{certificate_thumbprint, CertificateBindingMode},
+        context_enrichment::ContextClaim,
+        denylist::{DenylistClaim, DenylistEntries},
+        dpop::{
+            tests::{ath, es256_jkt, make_proof},
+            DpopMode,
+        },
+        introspection::DEFAULT_INTROSPECTION_TIMEOUT,
+        jwe::tests::encrypt,
+        models::{ClientCert, RequestContext, RequestIdentity},
+        rbac::{RbacRoute, DEFAULT_ROLE_CLAIMS},
+    };
     use chrono::{Duration, Utc};
     use httpmock::prelude::*;
-    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use jsonwebtoken::{Algorithm, EncodingKey};
     use reqwest::Url;
     use serde_json::json;
     use tracing_test::traced_test;
@@ -246,6 +708,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/*/GET/".to_string(),
+            ..Default::default()
         };
 
         // calls the handler service and get the response
@@ -385,10 +848,11 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: "NotBearer sometoken".to_string(),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
 
         let handler = make_simple_handler();
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -403,10 +867,11 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: "Bearer not_a_jwt".to_string(),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let handler = make_simple_handler();
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -415,6 +880,50 @@ mod tests {
         assert_eq!(statement.resource, "some_arn");
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn it_reports_the_deny_reason() {
+        let handler = make_simple_handler();
+        let mut decision = Decision::default();
+
+        let event = TokenAuthorizerEvent {
+            authorization_token: "NotBearer sometoken".to_string(),
+            ..Default::default()
+        };
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::MalformedScheme));
+
+        let event = TokenAuthorizerEvent {
+            authorization_token: "Bearer not_a_jwt".to_string(),
+            ..Default::default()
+        };
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::UndecodableHeader));
+        assert!(decision.kid.is_none());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_does_not_log_raw_tokens() {
+        let event = TokenAuthorizerEvent {
+            authorization_token: "Bearer not_a_jwt_secret".to_string(),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
+        let handler = make_simple_handler();
+
+        let response = handler.do_call(event, Default::default()).await;
+
+        assert!(response.is_ok());
+        assert!(logs_contain(&token_fingerprint("not_a_jwt_secret")));
+        assert!(logs_contain("reason=\"UndecodableHeader\""));
+        assert!(!logs_contain("not_a_jwt_secret"));
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn it_denies_if_the_token_uses_an_unsupported_algorithm() {
@@ -434,13 +943,14 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let accepted_signing_algorithms: AcceptedAlgorithms = "ES256".parse().unwrap();
         let accepted_signing_algorithms = Box::leak(Box::new(accepted_signing_algorithms));
         let mut handler = make_simple_handler();
         handler.accepted_signing_algorithms = accepted_signing_algorithms;
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -468,10 +978,11 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let handler = make_simple_handler();
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -508,6 +1019,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -516,7 +1028,7 @@ mod tests {
             None,
         )));
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -557,6 +1069,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -565,7 +1078,7 @@ mod tests {
             None,
         )));
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -605,6 +1118,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -613,7 +1127,7 @@ mod tests {
             None,
         )));
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -653,6 +1167,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -661,7 +1176,7 @@ mod tests {
             None,
         )));
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -702,6 +1217,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token),
             method_arn: "some_arn".to_string(),
+            ..Default::default()
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -713,7 +1229,7 @@ mod tests {
         let cel_validator: CelValidator = "claims.email_verified == true".parse().unwrap();
         handler.cel_validator = Box::leak(Box::new(cel_validator));
 
-        let response = handler.do_call(event).await;
+        let response = handler.do_call(event, Default::default()).await;
 
         assert!(response.is_ok());
         let response = response.unwrap();
@@ -724,7 +1240,7 @@ mod tests {
 
     #[tokio::test]
     #[traced_test]
-    async fn it_allows_if_cel_validation_passes() {
+    async fn it_denies_revoked_tokens() {
         let server = MockServer::start();
         let _jwks_mock = server.mock(|when, then| {
             when.method(GET).path("/");
@@ -735,8 +1251,6 @@ mod tests {
                     include_str!("../tests/fixtures/keys/rs256/jwk.json")
                 ));
         });
-        let iss = "http://localhost";
-        let aud = "test-app";
         let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
         let encoding_key =
             EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
@@ -745,15 +1259,18 @@ mod tests {
             json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
         )
         .unwrap();
-        // Token with email_verified: true - CEL expression will pass
-        let token = jsonwebtoken::encode(
-            &token_header,
-            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "preferred_username": "some_user", "email_verified": true }),
-            &encoding_key,
-        ).unwrap();
-        let event = TokenAuthorizerEvent {
-            authorization_token: format!("Bearer {}", token),
-            method_arn: "some_arn".to_string(),
+        let make_event = |jti: &str| {
+            let token = jsonwebtoken::encode(
+                &token_header,
+                &json!({ "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user", "jti": jti }),
+                &encoding_key,
+            )
+            .unwrap();
+            TokenAuthorizerEvent {
+                authorization_token: format!("Bearer {}", token),
+                method_arn: "some_arn".to_string(),
+                ..Default::default()
+            }
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -761,22 +1278,111 @@ mod tests {
             Duration::try_seconds(600).unwrap(),
             None,
         )));
-        // CEL expression that requires email_verified to be true
-        let cel_validator: CelValidator = "claims.email_verified == true".parse().unwrap();
+        let entries = DenylistEntries::load_from_str(&format!(
+            r#"{{"entries": [{{"claim": "jti", "value": "revoked-token", "exp": {}}}]}}"#,
+            exp
+        ))
+        .unwrap();
+        handler.denylist = Box::leak(Box::new(Denylist::new(entries, None, Duration::zero())));
+
+        let mut decision = Decision::default();
+        let event = make_event("revoked-token");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(
+            result.err(),
+            Some(DenyReason::Revoked {
+                claim: DenylistClaim::Jti
+            })
+        );
+
+        let event = make_event("valid-token");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_validates_opaque_tokens_using_introspection() {
+        let server = MockServer::start();
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let introspection_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/introspect")
+                .form_urlencoded_tuple("token", "opaque-token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "active": true, "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user" }));
+        });
+        let _other_app_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/introspect")
+                .form_urlencoded_tuple("token", "other-app-token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "active": true, "iss": "http://localhost", "aud": "other-app", "exp": exp }));
+        });
+        let _inactive_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/introspect")
+                .form_urlencoded_tuple("token", "inactive-token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "active": false }));
+        });
+        let mut handler = make_simple_handler();
+        handler.introspection = Box::leak(Box::new(Introspection::new(
+            Url::parse(&server.url("/introspect")).unwrap(),
+            "client".to_string(),
+            "secret".to_string(),
+            IntrospectionMode::Fallback,
+            100,
+            DEFAULT_INTROSPECTION_TIMEOUT,
+        )));
+        // opaque tokens have no header
+        let cel_validator: CelValidator = "size(header) == 0".parse().unwrap();
         handler.cel_validator = Box::leak(Box::new(cel_validator));
+        let make_event = |token: &str| TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
 
-        let response = handler.do_call(event).await;
+        for _ in 0..2 {
+            let response = handler
+                .clone()
+                .do_call(make_event("opaque-token"), Default::default())
+                .await
+                .unwrap();
+            assert_eq!(response.principal_id, "some_user");
+            assert_eq!(
+                response.policy_document.statement.first().unwrap().effect,
+                "Allow"
+            );
+        }
+        // the result is cached until the token expires
+        introspection_mock.assert_calls(1);
 
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        let statement = response.policy_document.statement.first().unwrap();
-        assert_eq!(statement.effect, "Allow");
-        assert_eq!(response.principal_id, "some_user");
+        let mut decision = Decision::default();
+        let event = make_event("other-app-token");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::BadAudience));
+
+        let event = make_event("inactive-token");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::InactiveToken));
     }
 
     #[tokio::test]
     #[traced_test]
-    async fn it_denies_if_cel_role_check_fails() {
+    async fn it_validates_dpop_bound_tokens() {
         let server = MockServer::start();
         let _jwks_mock = server.mock(|when, then| {
             when.method(GET).path("/");
@@ -787,25 +1393,44 @@ mod tests {
                     include_str!("../tests/fixtures/keys/rs256/jwk.json")
                 ));
         });
-        let iss = "http://localhost";
-        let aud = "test-app";
         let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
-        let encoding_key =
-            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
-                .unwrap();
         let token_header: Header = serde_json::from_value(
             json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
         )
         .unwrap();
-        // Token with roles that don't include "admin"
         let token = jsonwebtoken::encode(
             &token_header,
-            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "preferred_username": "some_user", "roles": ["user", "reader"] }),
-            &encoding_key,
-        ).unwrap();
-        let event = TokenAuthorizerEvent {
-            authorization_token: format!("Bearer {}", token),
-            method_arn: "some_arn".to_string(),
+            &json!({ "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user", "cnf": { "jkt": es256_jkt() } }),
+            &EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap(),
+        )
+        .unwrap();
+        let make_event = |scheme: &str, jti: &str, htm: &str| {
+            let proof = make_proof(json!({
+                "jti": jti,
+                "htm": htm,
+                "htu": "https://api.example.com/v1/pets",
+                "iat": Utc::now().timestamp(),
+                "ath": ath(&token),
+            }));
+            TokenAuthorizerEvent {
+                method_arn: "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/v1/GET/pets"
+                    .to_string(),
+                headers: Some(
+                    [
+                        ("Authorization".to_string(), format!("{} {}", scheme, token)),
+                        ("DPoP".to_string(), proof),
+                    ]
+                    .into(),
+                ),
+                http_method: Some("GET".to_string()),
+                request_context: Some(RequestContext {
+                    domain_name: Some("api.example.com".to_string()),
+                    path: Some("/v1/pets".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -813,22 +1438,56 @@ mod tests {
             Duration::try_seconds(600).unwrap(),
             None,
         )));
-        // CEL expression that requires admin role
-        let cel_validator: CelValidator =
-            r#"claims.roles.exists(r, r == "admin")"#.parse().unwrap();
-        handler.cel_validator = Box::leak(Box::new(cel_validator));
 
-        let response = handler.do_call(event).await;
+        // the DPoP scheme is disabled by default
+        let mut decision = Decision::default();
+        let event = make_event("DPoP", "proof-1", "GET");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::MalformedScheme));
 
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        let statement = response.policy_document.statement.first().unwrap();
-        assert_eq!(statement.effect, "Deny");
+        handler.dpop = Box::leak(Box::new(Dpop::new(
+            DpopMode::Allowed,
+            Duration::try_seconds(60).unwrap(),
+            100,
+        )));
+        let response = handler
+            .clone()
+            .do_call(event.clone(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.policy_document.statement.first().unwrap().effect,
+            "Allow"
+        );
+        // the proof is bound to the request, so the policy can't be reused for other methods
+        assert_eq!(
+            response.policy_document.statement[0].resource,
+            event.method_arn
+        );
+
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::DpopProofReplayed));
+
+        let event = make_event("DPoP", "proof-2", "POST");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::InvalidDpopProof));
+
+        let event = make_event("Bearer", "proof-3", "GET");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::DpopBindingMismatch));
     }
 
     #[tokio::test]
     #[traced_test]
-    async fn it_allows_if_cel_role_check_passes() {
+    async fn it_validates_certificate_bound_tokens() {
         let server = MockServer::start();
         let _jwks_mock = server.mock(|when, then| {
             when.method(GET).path("/");
@@ -839,25 +1498,37 @@ mod tests {
                     include_str!("../tests/fixtures/keys/rs256/jwk.json")
                 ));
         });
-        let iss = "http://localhost";
-        let aud = "test-app";
+        let client_cert_pem = include_str!("../tests/fixtures/certs/client.pem");
         let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
-        let encoding_key =
-            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
-                .unwrap();
         let token_header: Header = serde_json::from_value(
             json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
         )
         .unwrap();
-        // Token with roles that include "admin"
-        let token = jsonwebtoken::encode(
-            &token_header,
-            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "preferred_username": "some_user", "roles": ["user", "admin"] }),
-            &encoding_key,
-        ).unwrap();
-        let event = TokenAuthorizerEvent {
-            authorization_token: format!("Bearer {}", token),
-            method_arn: "some_arn".to_string(),
+        let make_token = |x5t: &str| {
+            jsonwebtoken::encode(
+                &token_header,
+                &json!({ "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user", "cnf": { "x5t#S256": x5t } }),
+                &EncodingKey::from_rsa_pem(include_bytes!(
+                    "../tests/fixtures/keys/rs256/private.pem"
+                ))
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let make_event = |token: &str, client_cert_pem: Option<&str>| TokenAuthorizerEvent {
+            method_arn: "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/v1/GET/pets"
+                .to_string(),
+            headers: Some([("Authorization".to_string(), format!("Bearer {}", token))].into()),
+            request_context: Some(RequestContext {
+                identity: Some(RequestIdentity {
+                    client_cert: client_cert_pem.map(|pem| ClientCert {
+                        client_cert_pem: pem.to_string(),
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
         };
         let mut handler = make_simple_handler();
         handler.keys = Box::leak(Box::new(KeysStorage::new(
@@ -865,20 +1536,761 @@ mod tests {
             Duration::try_seconds(600).unwrap(),
             None,
         )));
-        // CEL expression that requires admin role
-        let cel_validator: CelValidator =
-            r#"claims.roles.exists(r, r == "admin")"#.parse().unwrap();
-        handler.cel_validator = Box::leak(Box::new(cel_validator));
-
-        let response = handler.do_call(event).await;
+        handler.certificate_binding = Box::leak(Box::new(CertificateBinding::new(
+            CertificateBindingMode::Allowed,
+        )));
 
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        let statement = response.policy_document.statement.first().unwrap();
-        assert_eq!(statement.effect, "Allow");
+        let bound_token = make_token(&certificate_thumbprint(client_cert_pem).unwrap());
+        let event = make_event(&bound_token, Some(client_cert_pem));
+        let response = handler
+            .clone()
+            .do_call(event.clone(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.policy_document.statement.first().unwrap().effect,
+            "Allow"
+        );
+        // the decision depends on the client certificate, so the policy can't be reused for other methods
+        assert_eq!(
+            response.policy_document.statement[0].resource,
+            event.method_arn
+        );
+
+        let mut decision = Decision::default();
+        let event = make_event(&bound_token, None);
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::MissingClientCertificate));
+
+        // a token issued to another client
+        let event = make_event(&make_token("another-thumbprint"), Some(client_cert_pem));
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::CertificateBindingMismatch));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_validates_encrypted_tokens() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        let signed_token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user" }),
+            &EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap(),
+        )
+        .unwrap();
+        let make_event = |jwk: &str| TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", encrypt(jwk, &signed_token)),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+
+        // without private keys, encrypted tokens are not supported
+        let mut decision = Decision::default();
+        let event = make_event(include_str!("../tests/fixtures/keys/rsa-oaep-256/jwk.json"));
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::UndecodableHeader));
+
+        handler.jwe_decryptor = Box::leak(Box::new(
+            JweDecryptor::from_pem(include_str!(
+                "../tests/fixtures/keys/rsa-oaep-256/private.pem"
+            ))
+            .unwrap(),
+        ));
+        let response = handler
+            .clone()
+            .do_call(event, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.principal_id, "some_user");
+        assert_eq!(
+            response.policy_document.statement.first().unwrap().effect,
+            "Allow"
+        );
+
+        let event = make_event(include_str!("../tests/fixtures/keys/ecdh-es-p256/jwk.json"));
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::UndecryptableToken));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_introspects_encrypted_tokens_as_received() {
+        let server = MockServer::start();
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let encrypted_token = encrypt(
+            include_str!("../tests/fixtures/keys/rsa-oaep-256/jwk.json"),
+            "inner-token",
+        );
+        let introspection_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/introspect")
+                .form_urlencoded_tuple("token", &encrypted_token);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({ "active": true, "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user" }));
+        });
+        let mut handler = make_simple_handler();
+        handler.introspection = Box::leak(Box::new(Introspection::new(
+            Url::parse(&server.url("/introspect")).unwrap(),
+            "client".to_string(),
+            "secret".to_string(),
+            IntrospectionMode::Always,
+            100,
+            DEFAULT_INTROSPECTION_TIMEOUT,
+        )));
+        handler.jwe_decryptor = Box::leak(Box::new(
+            JweDecryptor::from_pem(include_str!(
+                "../tests/fixtures/keys/rsa-oaep-256/private.pem"
+            ))
+            .unwrap(),
+        ));
+
+        let event = TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", encrypted_token),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
+        let mut decision = Decision::default();
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert!(result.is_ok(), "{:?}", result.err());
+        introspection_mock.assert();
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_allows_if_cel_validation_passes() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let iss = "http://localhost";
+        let aud = "test-app";
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        // Token with email_verified: true - CEL expression will pass
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "preferred_username": "some_user", "email_verified": true }),
+            &encoding_key,
+        ).unwrap();
+        let event = TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        // CEL expression that requires email_verified to be true
+        let cel_validator: CelValidator = "claims.email_verified == true".parse().unwrap();
+        handler.cel_validator = Box::leak(Box::new(cel_validator));
+
+        let response = handler.do_call(event, Default::default()).await;
+
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        let statement = response.policy_document.statement.first().unwrap();
+        assert_eq!(statement.effect, "Allow");
+        // the expression only depends on the token, so the policy can be cached for every route
+        assert_eq!(statement.resource, "*");
+        assert_eq!(response.principal_id, "some_user");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_denies_if_cel_role_check_fails() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let iss = "http://localhost";
+        let aud = "test-app";
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        // Token with roles that don't include "admin"
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "preferred_username": "some_user", "roles": ["user", "reader"] }),
+            &encoding_key,
+        ).unwrap();
+        let event = TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        // CEL expression that requires admin role
+        let cel_validator: CelValidator =
+            r#"claims.roles.exists(r, r == "admin")"#.parse().unwrap();
+        handler.cel_validator = Box::leak(Box::new(cel_validator));
+
+        let response = handler.do_call(event, Default::default()).await;
+
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        let statement = response.policy_document.statement.first().unwrap();
+        assert_eq!(statement.effect, "Deny");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_allows_if_cel_role_check_passes() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let iss = "http://localhost";
+        let aud = "test-app";
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        // Token with roles that include "admin"
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "preferred_username": "some_user", "roles": ["user", "admin"] }),
+            &encoding_key,
+        ).unwrap();
+        let event = TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        // CEL expression that requires admin role
+        let cel_validator: CelValidator =
+            r#"claims.roles.exists(r, r == "admin")"#.parse().unwrap();
+        handler.cel_validator = Box::leak(Box::new(cel_validator));
+
+        let response = handler.do_call(event, Default::default()).await;
+
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        let statement = response.policy_document.statement.first().unwrap();
+        assert_eq!(statement.effect, "Allow");
         assert_eq!(response.principal_id, "some_user");
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn it_validates_request_events_using_request_metadata_in_cel() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let iss = "http://localhost";
+        let aud = "test-app";
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "tenant": "acme" }),
+            &encoding_key,
+        )
+        .unwrap();
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        // the tenant claim must match the X-Tenant header
+        let cel_validator: CelValidator =
+            r#"claims.tenant == request.headers["x-tenant"]"#.parse().unwrap();
+        handler.cel_validator = Box::leak(Box::new(cel_validator));
+
+        let make_event = |tenant: &str| -> TokenAuthorizerEvent {
+            serde_json::from_value(json!({
+                "type": "REQUEST",
+                "methodArn": "arn:aws:execute-api:us-east-1:123456789012:api/prod/GET/pets",
+                "path": "/pets",
+                "httpMethod": "GET",
+                "headers": { "Authorization": format!("Bearer {}", token), "X-Tenant": tenant },
+                "requestContext": { "stage": "prod", "identity": { "sourceIp": "10.0.0.1" } }
+            }))
+            .unwrap()
+        };
+
+        let response = handler
+            .clone()
+            .do_call(make_event("acme"), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+        // the expression depends on the request, so the policy is scoped to the method ARN
+        assert_eq!(
+            response.policy_document.statement[0].resource,
+            "arn:aws:execute-api:us-east-1:123456789012:api/prod/GET/pets"
+        );
+
+        let response = handler
+            .do_call(make_event("other"), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].effect, "Deny");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_computes_principal_id_and_context_with_cel() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let iss = "http://localhost";
+        let aud = "test-app";
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let encoding_key =
+            EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": iss, "aud": aud, "exp": exp, "sub": "some_user", "tenant": "acme" }),
+            &encoding_key,
+        )
+        .unwrap();
+        let event = TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: "arn:aws:execute-api:us-east-1:123456789012:api/prod/GET/pets".to_string(),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        handler.principal_id_claims = Box::leak(Box::new(
+            PrincipalIDClaims::from_comma_separated_values("sub", "unknown".to_string())
+                .with_cel(r#"claims.tenant + ":" + claims.sub"#.parse().unwrap()),
+        ));
+        let handler = handler.with_context_enrichment(Box::leak(Box::new(
+            ContextEnrichment::default().with_cel(
+                r#"{"tenant": claims.tenant, "stage": request.stage}"#
+                    .parse()
+                    .unwrap(),
+            ),
+        )));
+
+        let response = handler
+            .clone()
+            .do_call(event.clone(), Default::default())
+            .await
+            .unwrap();
+
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+        assert_eq!(response.principal_id, "acme:some_user");
+        assert_eq!(response.context.get("tenant").unwrap(), "acme");
+        assert_eq!(response.context.get("stage").unwrap(), "prod");
+        assert!(response.context.contains_key("jwtClaims"));
+        // the context reads the request, so the policy is scoped to the method
+        assert_eq!(
+            response.policy_document.statement[0].resource,
+            event.method_arn
+        );
+
+        // individual claims only
+        let handler = handler.with_context_enrichment(Box::leak(Box::new(
+            ContextEnrichment::default()
+                .with_claims(
+                    ContextClaim::from_comma_separated_values("sub->userId, tenant").unwrap(),
+                )
+                .with_jwt_claims(false),
+        )));
+
+        let response = handler
+            .clone()
+            .do_call(event.clone(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].resource, "*");
+
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+        assert_eq!(response.context.get("userId").unwrap(), "some_user");
+        assert_eq!(response.context.get("tenant").unwrap(), "acme");
+        assert!(!response.context.contains_key("jwtClaims"));
+
+        // the usage identifier key is taken from the given claim
+        let handler = handler.with_usage_identifier(Box::leak(Box::new(UsageIdentifier::new(
+            Some("tenant".to_string()),
+            true,
+        ))));
+        let response = handler
+            .clone()
+            .do_call(event.clone(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.usage_identifier_key, Some("acme".to_string()));
+
+        let mut decision = Decision::default();
+        let result = handler
+            .clone()
+            .with_usage_identifier(Box::leak(Box::new(UsageIdentifier::new(
+                Some("api_key".to_string()),
+                true,
+            ))))
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::MissingUsageIdentifierKey));
+
+        // responses exceeding the size limit are denied
+        let handler = handler
+            .with_response_size_guard(Box::leak(Box::new(ResponseSizeGuard::new(100, Vec::new()))));
+        let mut decision = Decision::default();
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::ResponseTooLarge));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_validates_issuer_patterns_and_exposes_the_tenant() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        let make_event = |iss: &str| {
+            TokenAuthorizerEvent {
+            authorization_token: format!(
+                "Bearer {}",
+                jsonwebtoken::encode(
+                    &token_header,
+                    &json!({ "iss": iss, "aud": "test-app", "exp": exp, "sub": "some_user", "appid": "app-1" }),
+                    &EncodingKey::from_rsa_pem(include_bytes!(
+                        "../tests/fixtures/keys/rs256/private.pem"
+                    ))
+                    .unwrap(),
+                )
+                .unwrap()
+            ),
+            method_arn: "some_arn".to_string(),
+            ..Default::default()
+        }
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        handler.accepted_issuers =
+            Box::leak(Box::new(AcceptedClaims::from_comma_separated_values(
+                "https://login.example.com/{tenant}/v2.0",
+                "iss".to_string(),
+            )));
+        handler.cel_validator = Box::leak(Box::new(
+            r#"request.tenant in ["acme", "globex"]"#.parse().unwrap(),
+        ));
+
+        let response = handler
+            .clone()
+            .do_call(
+                make_event("https://login.example.com/acme/v2.0"),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+        // the tenant is only added to the context if a key is configured
+        assert!(!response.context.contains_key("tenant"));
+
+        handler.context_enrichment = Box::leak(Box::new(
+            ContextEnrichment::default().with_tenant_key("tenantId".to_string()),
+        ));
+        let response = handler
+            .clone()
+            .do_call(
+                make_event("https://login.example.com/acme/v2.0"),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.context.get("tenantId").unwrap(), "acme");
+
+        // the tenant is not accepted by the CEL expression
+        let mut decision = Decision::default();
+        let event = make_event("https://login.example.com/initech/v2.0");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::CelRejected { rule: None }));
+
+        let event = make_event("https://login.example.com/acme/v1.0");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::BadIssuer));
+
+        // only the given clients are accepted
+        let handler = handler.with_accepted_client_ids(Box::leak(Box::new(
+            AcceptedClientIds::from_comma_separated_values("app-1", DEFAULT_CLIENT_ID_CLAIMS),
+        )));
+        let event = make_event("https://login.example.com/acme/v2.0");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(result.err(), Some(DenyReason::BadClientId));
+
+        // the client ID claim can be overridden for the issuer pattern
+        let handler = handler.with_accepted_client_ids(Box::leak(Box::new(
+            AcceptedClientIds::from_comma_separated_values("app-1", DEFAULT_CLIENT_ID_CLAIMS)
+                .with_issuer_claims("https://login.example.com/{tenant}/v2.0=appid")
+                .unwrap(),
+        )));
+        let response = handler
+            .clone()
+            .do_call(event, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_validates_roles_for_routes() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user", "realm_access": { "roles": ["support", "users"] } }),
+            &EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap(),
+        )
+        .unwrap();
+        let make_event = |method: &str, path: &str| TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: format!(
+                "arn:aws:execute-api:us-east-1:123456789012:api/prod/{}{}",
+                method, path
+            ),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+        handler.cel_validator = Box::leak(Box::new(
+            r#"request.method == "GET" || "support" in request.roles"#
+                .parse()
+                .unwrap(),
+        ));
+        let handler = handler.with_rbac(Box::leak(Box::new(Rbac::from_comma_separated_values(
+            DEFAULT_ROLE_CLAIMS,
+            RbacRoute::load_from_str(
+                r#"{"routes": [{"route": "/admin/*", "roles": ["admin", "support"]}, {"route": "DELETE /*", "roles": ["admin"]}]}"#,
+            )
+            .unwrap(),
+        ))));
+
+        let response = handler
+            .clone()
+            .do_call(make_event("POST", "/admin/tickets"), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+        assert_eq!(response.context.get("roles").unwrap(), "support,users");
+
+        let mut decision = Decision::default();
+        let event = make_event("DELETE", "/pets/1");
+        let result = handler
+            .authorize(&event, &event.request_metadata(), &mut decision)
+            .await;
+        assert_eq!(
+            result.err(),
+            Some(DenyReason::InsufficientRoles {
+                route: "DELETE /*".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_scopes_the_allow_policy_to_the_method_when_routes_require_roles() {
+        let server = MockServer::start();
+        let _jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    "{{\"keys\":[ {} ]}}",
+                    include_str!("../tests/fixtures/keys/rs256/jwk.json")
+                ));
+        });
+        let exp = (Utc::now() + Duration::try_hours(1).unwrap()).timestamp();
+        let token_header: Header = serde_json::from_value(
+            json!({ "alg": Algorithm::RS256, "kid": "test/keys/rs256/public" }),
+        )
+        .unwrap();
+        let token = jsonwebtoken::encode(
+            &token_header,
+            &json!({ "iss": "http://localhost", "aud": "test-app", "exp": exp, "sub": "some_user", "groups": ["admin"] }),
+            &EncodingKey::from_rsa_pem(include_bytes!("../tests/fixtures/keys/rs256/private.pem"))
+                .unwrap(),
+        )
+        .unwrap();
+        let method_arn = "arn:aws:execute-api:us-east-1:123456789012:api/prod/DELETE/pets/1";
+        let event = TokenAuthorizerEvent {
+            authorization_token: format!("Bearer {}", token),
+            method_arn: method_arn.to_string(),
+            ..Default::default()
+        };
+        let mut handler = make_simple_handler();
+        handler.keys = Box::leak(Box::new(KeysStorage::new(
+            Url::parse(&server.url("/")).unwrap(),
+            Duration::try_seconds(600).unwrap(),
+            None,
+        )));
+
+        // without routes, the allow policy can be reused for every route
+        let response = handler
+            .clone()
+            .do_call(event.clone(), Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].resource, "*");
+        assert!(!response.context.contains_key("roles"));
+
+        let handler = handler.with_rbac(Box::leak(Box::new(Rbac::from_comma_separated_values(
+            DEFAULT_ROLE_CLAIMS,
+            RbacRoute::load_from_str(r#"{"routes": [{"route": "DELETE /*", "roles": ["admin"]}]}"#)
+                .unwrap(),
+        ))));
+        let response = handler
+            .clone()
+            .do_call(event, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(response.policy_document.statement[0].effect, "Allow");
+        assert_eq!(response.policy_document.statement[0].resource, method_arn);
+        assert_eq!(response.context.get("roles").unwrap(), "admin");
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn it_validates_tokens_across_pre_warmed_cache_and_network_refresh() {
@@ -951,6 +2363,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token1),
             method_arn: "arn:aws:execute-api:us-east-1:123456789012:api/*/GET/".to_string(),
+            ..Default::default()
         };
         let response = handler
             .call(LambdaEvent::new(event, Default::default()))
@@ -976,6 +2389,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token2),
             method_arn: "arn:aws:execute-api:us-east-1:123456789012:api/*/GET/".to_string(),
+            ..Default::default()
         };
         let response = handler
             .call(LambdaEvent::new(event, Default::default()))
@@ -1001,6 +2415,7 @@ mod tests {
         let event = TokenAuthorizerEvent {
             authorization_token: format!("Bearer {}", token3),
             method_arn: "arn:aws:execute-api:us-east-1:123456789012:api/*/GET/".to_string(),
+            ..Default::default()
         };
         let response = handler
             .call(LambdaEvent::new(event, Default::default()))
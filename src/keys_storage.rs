@@ -2,7 +2,14 @@ use crate::keysmap::KeysMap;
 use chrono::{DateTime, Duration, Utc};
 use jsonwebtoken::{jwk::JwkSet, DecodingKey};
 use reqwest::{Client, Url};
-use std::{fs::File, path::PathBuf, sync::Arc};
+use std::{
+    fs::File,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
 use tokio::sync::RwLock;
 
@@ -16,6 +23,14 @@ pub enum KeysStorageError {
     KeyNotFound(String),
 }
 
+/// Counters about the JWKS cache activity since the last call to [`KeysStorage::take_stats`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeysStorageStats {
+    pub refreshes: u64,
+    pub refresh_failures: u64,
+    pub pre_warmed_cache_misses: u64,
+}
+
 #[derive(Debug)]
 pub struct KeysStorage {
     jwks_uri: Url,
@@ -23,6 +38,9 @@ pub struct KeysStorage {
     storage: Arc<RwLock<(KeysMap, DateTime<Utc>)>>,
     min_refresh_rate: Duration,
     pre_warmed: bool,
+    refreshes: AtomicU64,
+    refresh_failures: AtomicU64,
+    pre_warmed_cache_misses: AtomicU64,
 }
 
 impl KeysStorage {
@@ -67,23 +85,54 @@ impl KeysStorage {
                 .unwrap(),
             storage: Arc::new(RwLock::new((initial_keys, Default::default()))),
             pre_warmed,
+            refreshes: AtomicU64::new(0),
+            refresh_failures: AtomicU64::new(0),
+            pre_warmed_cache_misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the current stats and resets all the counters
+    pub fn take_stats(&self) -> KeysStorageStats {
+        KeysStorageStats {
+            refreshes: self.refreshes.swap(0, Ordering::Relaxed),
+            refresh_failures: self.refresh_failures.swap(0, Ordering::Relaxed),
+            pre_warmed_cache_misses: self.pre_warmed_cache_misses.swap(0, Ordering::Relaxed),
         }
     }
 
-    pub async fn get(&self, key_id: &str) -> Result<DecodingKey, KeysStorageError> {
+    /// Returns the key with the given ID, refreshing the JWKS if needed.
+    /// The X-Ray trace header (if any) is propagated to the JWKS endpoint as `X-Amzn-Trace-Id`.
+    #[tracing::instrument(
+        name = "jwks.get",
+        skip(self, trace_header),
+        fields(jwks.kid = key_id, jwks.cache_hit = tracing::field::Empty)
+    )]
+    pub async fn get(
+        &self,
+        key_id: &str,
+        trace_header: Option<&str>,
+    ) -> Result<DecodingKey, KeysStorageError> {
+        let span = tracing::Span::current();
         let read_guard = self.storage.read().await;
         if let Some(key) = read_guard.0.get(key_id) {
+            span.record("jwks.cache_hit", true);
             return Ok(key.clone());
         }
+        span.record("jwks.cache_hit", false);
 
         let should_refresh = read_guard.1 + self.min_refresh_rate < Utc::now();
         drop(read_guard);
 
         if should_refresh {
-            self.refresh().await?;
+            if let Err(e) = self.refresh(trace_header).await {
+                self.refresh_failures.fetch_add(1, Ordering::Relaxed);
+                return Err(e);
+            }
+            self.refreshes.fetch_add(1, Ordering::Relaxed);
             let read_guard = self.storage.read().await;
             if let Some(key) = read_guard.0.get(key_id) {
                 if self.pre_warmed {
+                    self.pre_warmed_cache_misses.fetch_add(1, Ordering::Relaxed);
                     tracing::warn!(
                         event_type = "jwks_refresh_needed",
                         jwks_uri = %self.jwks_uri,
@@ -100,13 +149,27 @@ impl KeysStorage {
         Err(KeysStorageError::KeyNotFound(key_id.to_string()))
     }
 
-    async fn refresh(&self) -> Result<(), KeysStorageError> {
+    #[tracing::instrument(
+        name = "jwks.refresh",
+        skip(self, trace_header),
+        fields(
+            http.request.method = "GET",
+            url.full = %self.jwks_uri,
+            http.response.status_code = tracing::field::Empty
+        )
+    )]
+    async fn refresh(&self, trace_header: Option<&str>) -> Result<(), KeysStorageError> {
         tracing::debug!("Refreshing JWKS from '{}'", self.jwks_uri.as_ref());
-        let res = self.client.get(self.jwks_uri.as_ref()).send().await?;
+        let mut req = self.client.get(self.jwks_uri.as_ref());
+        if let Some(trace_header) = trace_header {
+            req = req.header("X-Amzn-Trace-Id", trace_header);
+        }
+        let res = req.send().await?;
+        tracing::Span::current().record("http.response.status_code", res.status().as_u16());
         tracing::debug!("JWKS fetched got status: {}", res.status());
         let jwks = res.text().await?;
-        tracing::debug!("JWKS fetched got body: {}", jwks);
         let jwks: JwkSet = serde_json::from_str(&jwks)?;
+        tracing::debug!("JWKS fetched got {} keys", jwks.keys.len());
 
         let mut write_guard = self.storage.write().await;
         write_guard.0 = jwks.into();
@@ -167,12 +230,12 @@ mod tests {
         // SAFETY: safe to unwrap since (60 seconds) <= (i64::MAX / 1000)
         let min_refresh_rate = Duration::try_seconds(60).unwrap();
         let keys_cache = KeysStorage::new(jwks_uri.clone(), min_refresh_rate, None);
-        let key_result = keys_cache.get(key_id).await;
+        let key_result = keys_cache.get(key_id, None).await;
         assert!(key_result.is_ok());
         jwks_mock.assert_calls(1);
 
         // if it reads the key again it should be taken straight away from cache
-        let key_result = keys_cache.get(key_id).await;
+        let key_result = keys_cache.get(key_id, None).await;
         assert!(key_result.is_ok());
         jwks_mock.assert_calls(1); // no new hits
     }
@@ -206,7 +269,7 @@ mod tests {
         // SAFETY: safe to unwrap since (60 seconds) <= (i64::MAX / 1000)
         let min_refresh_rate = Duration::try_seconds(60).unwrap();
         let keys_cache = KeysStorage::new(jwks_uri.clone(), min_refresh_rate, None);
-        let key_result = keys_cache.get(key_id).await;
+        let key_result = keys_cache.get(key_id, None).await;
         if let Err(KeysStorageError::KeyNotFound(_)) = key_result {
             // expected
         } else {
@@ -248,7 +311,7 @@ mod tests {
             Some(PathBuf::from("/nonexistent/path/jwks.json")),
         );
         assert!(!keys_cache.pre_warmed);
-        let key_result = keys_cache.get("test/keys/rs256/public").await;
+        let key_result = keys_cache.get("test/keys/rs256/public", None).await;
         assert!(key_result.is_ok());
         jwks_mock.assert();
     }
@@ -284,7 +347,7 @@ mod tests {
         std::fs::write(&path, "{{not valid json").unwrap();
         let keys_cache = KeysStorage::new(jwks_uri.clone(), min_refresh_rate, Some(path));
         assert!(!keys_cache.pre_warmed);
-        let key_result = keys_cache.get("test/keys/rs256/public").await;
+        let key_result = keys_cache.get("test/keys/rs256/public", None).await;
         assert!(key_result.is_ok());
         jwks_mock.assert();
     }
@@ -324,7 +387,7 @@ mod tests {
         let min_refresh_rate = Duration::try_seconds(60).unwrap();
         let keys_cache = KeysStorage::new(jwks_uri.clone(), min_refresh_rate, Some(path));
         assert!(!keys_cache.pre_warmed);
-        let key_result = keys_cache.get("test/keys/rs256/public").await;
+        let key_result = keys_cache.get("test/keys/rs256/public", None).await;
         assert!(key_result.is_ok());
         jwks_mock.assert();
     }
@@ -360,7 +423,7 @@ mod tests {
         let keys_cache = KeysStorage::new(jwks_uri.clone(), min_refresh_rate, Some(path));
         assert!(keys_cache.pre_warmed);
 
-        let key_result = keys_cache.get("test/keys/rs256/public").await;
+        let key_result = keys_cache.get("test/keys/rs256/public", None).await;
         assert!(key_result.is_ok());
         jwks_mock.assert_calls(0);
     }
@@ -395,18 +458,26 @@ mod tests {
         assert!(keys_cache.pre_warmed);
 
         // Step 1: key1 (rs256) — should be served from pre-warmed cache, no network call
-        let result = keys_cache.get("test/keys/rs256/public").await;
+        let result = keys_cache.get("test/keys/rs256/public", None).await;
         assert!(result.is_ok());
         jwks_mock.assert_calls(0);
 
         // Step 2: key2 (rs384) — cache miss, triggers network refresh
-        let result = keys_cache.get("test/keys/rs384/public").await;
+        let result = keys_cache.get("test/keys/rs384/public", None).await;
         assert!(result.is_ok());
         jwks_mock.assert_calls(1);
         assert!(logs_contain("jwks_refresh_needed"));
+        assert_eq!(
+            keys_cache.take_stats(),
+            KeysStorageStats {
+                refreshes: 1,
+                refresh_failures: 0,
+                pre_warmed_cache_misses: 1,
+            }
+        );
 
         // Step 3: key3 (rs512) — should already be in cache from step 2's refresh
-        let result = keys_cache.get("test/keys/rs512/public").await;
+        let result = keys_cache.get("test/keys/rs512/public", None).await;
         assert!(result.is_ok());
         jwks_mock.assert_calls(1); // no additional network call
     }
@@ -431,9 +502,64 @@ mod tests {
         assert!(!keys_cache.pre_warmed);
 
         // Cache miss triggers network refresh, but no refresh event
-        let result = keys_cache.get("test/keys/rs256/public").await;
+        let result = keys_cache.get("test/keys/rs256/public", None).await;
         assert!(result.is_ok());
         jwks_mock.assert_calls(1);
         assert!(!logs_contain("jwks_refresh_needed"));
     }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_should_count_refresh_failures_and_reset_stats() {
+        let server = MockServer::start();
+        let jwks_mock = server.mock(|when, then| {
+            when.method(GET).path("/");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body("not json");
+        });
+
+        let jwks_uri = Url::parse(server.url("/").as_str()).unwrap();
+        let keys_cache = KeysStorage::new(jwks_uri, Duration::zero(), None);
+
+        assert!(keys_cache.get("some-key", None).await.is_err());
+        assert!(keys_cache.get("some-key", None).await.is_err());
+        jwks_mock.assert_calls(2);
+        assert_eq!(
+            keys_cache.take_stats(),
+            KeysStorageStats {
+                refreshes: 0,
+                refresh_failures: 2,
+                pre_warmed_cache_misses: 0,
+            }
+        );
+        assert_eq!(keys_cache.take_stats(), KeysStorageStats::default());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn it_should_propagate_the_trace_header_when_refreshing() {
+        let rs256_jwk = include_str!("../tests/fixtures/keys/rs256/jwk.json");
+        let trace_header = "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1";
+
+        let server = MockServer::start();
+        let jwks_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/")
+                .header("X-Amzn-Trace-Id", trace_header);
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(format!(r#"{{"keys":[{}]}}"#, rs256_jwk));
+        });
+
+        let jwks_uri = Url::parse(server.url("/").as_str()).unwrap();
+        let keys_cache = KeysStorage::new(jwks_uri, Duration::try_seconds(60).unwrap(), None);
+
+        let result = keys_cache
+            .get("test/keys/rs256/public", Some(trace_header))
+            .await;
+        assert!(result.is_ok());
+        jwks_mock.assert_calls(1);
+        assert!(logs_contain("jwks.refresh"));
+    }
 }
@@ -0,0 +1,170 @@
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{collections::HashSet, str::FromStr};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LogRedactionError {
+    #[error("Invalid log redaction level '{0}'. Supported values: redacted, verbose")]
+    InvalidLevel(String),
+}
+
+/// How much sensitive information (tokens and claims) can end up in the logs
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum LogRedactionLevel {
+    /// Tokens are replaced by a fingerprint and only allowlisted claims are logged
+    #[default]
+    Redacted,
+    /// Tokens and claims are logged as they are (only meant for local debugging)
+    Verbose,
+}
+
+impl FromStr for LogRedactionLevel {
+    type Err = LogRedactionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "redacted" => Ok(LogRedactionLevel::Redacted),
+            "verbose" => Ok(LogRedactionLevel::Verbose),
+            _ => Err(LogRedactionError::InvalidLevel(s.to_string())),
+        }
+    }
+}
+
+/// Returns a short, non-reversible identifier for a token (the prefix of its SHA-256 hash)
+/// that can be used to correlate log entries without leaking the token itself.
+pub fn token_fingerprint(token: &str) -> String {
+    let hash = Sha256::digest(token.as_bytes());
+    let prefix: String = hash[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256:{}", prefix)
+}
+
+/// The claims that are logged by default (when redaction is enabled)
+pub const DEFAULT_ALLOWED_CLAIMS: &str = "iss, aud, azp, client_id, exp, iat, nbf";
+
+#[derive(Debug, Clone)]
+pub struct LogRedactor {
+    level: LogRedactionLevel,
+    allowed_claims: HashSet<String>,
+}
+
+impl Default for LogRedactor {
+    fn default() -> Self {
+        Self::from_comma_separated_values(LogRedactionLevel::default(), DEFAULT_ALLOWED_CLAIMS)
+    }
+}
+
+impl LogRedactor {
+    pub fn from_comma_separated_values(level: LogRedactionLevel, allowed_claims: &str) -> Self {
+        let allowed_claims = allowed_claims
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        Self {
+            level,
+            allowed_claims,
+        }
+    }
+
+    /// Returns a loggable representation of a token
+    pub fn token(&self, token: &str) -> String {
+        match self.level {
+            LogRedactionLevel::Verbose => token.to_string(),
+            LogRedactionLevel::Redacted => token_fingerprint(token),
+        }
+    }
+
+    /// Returns a loggable representation of an authorization header, keeping only the scheme
+    pub fn authorization_header(&self, header: &str) -> String {
+        match (self.level, header.split_once(' ')) {
+            (LogRedactionLevel::Verbose, _) => header.to_string(),
+            (LogRedactionLevel::Redacted, Some((scheme, credentials))) => {
+                format!("{} {}", scheme, token_fingerprint(credentials))
+            }
+            (LogRedactionLevel::Redacted, None) => token_fingerprint(header),
+        }
+    }
+
+    /// Returns a loggable version of the claims containing only the allowlisted claims
+    pub fn claims(&self, claims: &Value) -> Value {
+        match (self.level, claims) {
+            (LogRedactionLevel::Redacted, Value::Object(claims)) => Value::Object(
+                claims
+                    .iter()
+                    .filter(|(name, _)| self.allowed_claims.contains(name.as_str()))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect::<Map<_, _>>(),
+            ),
+            _ => claims.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_should_parse_the_redaction_level() {
+        assert_eq!(
+            "".parse::<LogRedactionLevel>().unwrap(),
+            LogRedactionLevel::Redacted
+        );
+        assert_eq!(
+            "Verbose".parse::<LogRedactionLevel>().unwrap(),
+            LogRedactionLevel::Verbose
+        );
+        assert!("none".parse::<LogRedactionLevel>().is_err());
+    }
+
+    #[test]
+    fn it_should_fingerprint_tokens() {
+        let fingerprint = token_fingerprint("some.token.value");
+        assert!(fingerprint.starts_with("sha256:"));
+        assert_eq!(fingerprint.len(), "sha256:".len() + 12);
+        assert_eq!(fingerprint, token_fingerprint("some.token.value"));
+        assert_ne!(fingerprint, token_fingerprint("another.token.value"));
+    }
+
+    #[test]
+    fn it_should_redact_tokens_and_headers() {
+        let redactor = LogRedactor::default();
+        assert!(!redactor.token("secret.token").contains("secret"));
+        assert_eq!(
+            redactor.authorization_header("Basic c2VjcmV0"),
+            format!("Basic {}", token_fingerprint("c2VjcmV0"))
+        );
+        assert!(!redactor.authorization_header("secret").contains("secret"));
+
+        let redactor = LogRedactor::from_comma_separated_values(LogRedactionLevel::Verbose, "");
+        assert_eq!(redactor.token("secret.token"), "secret.token");
+        assert_eq!(
+            redactor.authorization_header("Basic c2VjcmV0"),
+            "Basic c2VjcmV0"
+        );
+    }
+
+    #[test]
+    fn it_should_only_keep_allowlisted_claims() {
+        let claims =
+            json!({"iss": "https://example.com", "sub": "user-123", "email": "john@example.com"});
+
+        let redactor = LogRedactor::default();
+        assert_eq!(
+            redactor.claims(&claims),
+            json!({"iss": "https://example.com"})
+        );
+
+        let redactor =
+            LogRedactor::from_comma_separated_values(LogRedactionLevel::Redacted, "iss, sub");
+        assert_eq!(
+            redactor.claims(&claims),
+            json!({"iss": "https://example.com", "sub": "user-123"})
+        );
+
+        let redactor = LogRedactor::from_comma_separated_values(LogRedactionLevel::Verbose, "");
+        assert_eq!(redactor.claims(&claims), claims);
+    }
+}
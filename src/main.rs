@@ -1,70 +1,485 @@
 use accepted_algorithms::AcceptedAlgorithms;
 use accepted_claims::AcceptedClaims;
-use cel_validation::CelValidator;
+use accepted_client_ids::{AcceptedClientIds, DEFAULT_CLIENT_ID_CLAIMS};
+use audit_log::AuditLog;
+use cel_self_test::CelSelfTest;
+use cel_validation::{CelRule, CelValidator};
+use certificate_binding::CertificateBinding;
 use chrono::Duration;
+use claim_constraints::ClaimConstraints;
+use config::Config;
+use config_check::{ConfigCheck, ConfigReport, IssueSeverity};
+use context_enrichment::{ContextClaim, ContextEnrichment};
+use denylist::{Denylist, DenylistEntries};
+use dpop::Dpop;
+use introspection::{Introspection, DEFAULT_INTROSPECTION_TIMEOUT};
+use jwe::JweDecryptor;
 use keys_storage::KeysStorage;
 use lambda_runtime::{run, tracing, Error};
+use log_redaction::{LogRedactor, DEFAULT_ALLOWED_CLAIMS};
+use metrics::Metrics;
 use principalid_claims::PrincipalIDClaims;
+use rbac::{Rbac, RbacRoute, DEFAULT_ROLE_CLAIMS};
 use reqwest::Url;
+use response_size::{ReducibleField, ResponseSizeGuard};
 use std::{env, path::PathBuf};
+use telemetry::Telemetry;
+use usage_identifier::UsageIdentifier;
 
 mod accepted_algorithms;
 mod accepted_claims;
+mod accepted_client_ids;
+mod audit_log;
+mod cel_functions;
+mod cel_self_test;
 mod cel_validation;
+mod certificate_binding;
+mod claim_constraints;
+mod claim_path;
+mod config;
+mod config_check;
+mod context_enrichment;
+mod decision;
+mod denylist;
+mod dpop;
+mod glob_pattern;
 mod handler;
+mod introspection;
+mod jwe;
 mod keys_storage;
 mod keysmap;
+mod log_redaction;
+mod method_arn;
+mod metrics;
 mod models;
 mod parse_token_from_header;
 mod principalid_claims;
+mod rbac;
+mod response_size;
+mod telemetry;
+mod usage_identifier;
 
-fn maybe_get_jwks_cache_path() -> Option<PathBuf> {
-    env::var("JWKS_PRE_CACHED_FILE_PATH")
+fn maybe_get_jwks_cache_path(config: &Config) -> Option<PathBuf> {
+    config
+        .var("JWKS_PRE_CACHED_FILE_PATH")
         .ok()
         .map(PathBuf::from)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    let jwks_uri = env::var("JWKS_URI")?;
-    let jwks_uri: Url = jwks_uri.parse()?;
-    let min_refresh_rate = env::var("MIN_REFRESH_RATE").unwrap_or("900".to_string());
-    let min_refresh_rate =
-        Duration::try_seconds(min_refresh_rate.parse::<u64>()? as i64).ok_or(format!(
-            "Invalid MIN_REFRESH_RATE value provided. '{}' should be less than {}",
-            min_refresh_rate,
+/// Reads a duration setting (in seconds)
+fn get_seconds(config: &Config, name: &str, default: &str) -> Result<Duration, Error> {
+    let value = config.var_or(name, default);
+    Duration::try_seconds(value.parse::<u64>()? as i64).ok_or_else(|| {
+        format!(
+            "Invalid {} value provided. '{}' should be less than {}",
+            name,
+            value,
             i64::MAX / 1000
-        ))?;
+        )
+        .into()
+    })
+}
+
+/// Reads a (non-zero) duration setting in milliseconds
+fn get_millis(
+    config: &Config,
+    name: &str,
+    default: std::time::Duration,
+) -> Result<std::time::Duration, Error> {
+    let millis = match config.var(name) {
+        Ok(value) if !value.trim().is_empty() => value.trim().parse::<u64>()?,
+        _ => return Ok(default),
+    };
+    if millis == 0 {
+        return Err(format!(
+            "Invalid {} value provided. It should be greater than 0",
+            name
+        )
+        .into());
+    }
+    Ok(std::time::Duration::from_millis(millis))
+}
+
+fn get_denylist(config: &Config, report: &mut ConfigReport) -> Denylist {
+    let static_entries = match config.var("DENYLIST_FILE") {
+        Ok(path) if !path.is_empty() => {
+            report.check("DENYLIST_FILE", DenylistEntries::load_from_file(path))
+        }
+        _ => DenylistEntries::default(),
+    };
+    let url = match config.var("DENYLIST_URL") {
+        Ok(url) if !url.is_empty() => report.check("DENYLIST_URL", Url::parse(&url).map(Some)),
+        _ => None,
+    };
+    let refresh_rate = report.check(
+        "DENYLIST_REFRESH_RATE",
+        get_seconds(config, "DENYLIST_REFRESH_RATE", "60"),
+    );
+
+    Denylist::new(static_entries, url, refresh_rate)
+}
+
+fn get_dpop(config: &Config, report: &mut ConfigReport) -> Dpop {
+    let mode = report.check(
+        "DPOP_MODE",
+        config.var("DPOP_MODE").unwrap_or_default().parse(),
+    );
+    let max_age = report.check("DPOP_MAX_AGE", get_seconds(config, "DPOP_MAX_AGE", "300"));
+    let replay_cache_size = report.check(
+        "DPOP_REPLAY_CACHE_SIZE",
+        config
+            .var("DPOP_REPLAY_CACHE_SIZE")
+            .unwrap_or("10000".to_string())
+            .parse::<usize>(),
+    );
+
+    Dpop::new(mode, max_age, replay_cache_size)
+}
+
+fn get_introspection(config: &Config, report: &mut ConfigReport) -> Introspection {
+    let endpoint = config.var("INTROSPECTION_ENDPOINT").unwrap_or_default();
+    if endpoint.trim().is_empty() {
+        return Introspection::default();
+    }
+
+    let endpoint = report.check("INTROSPECTION_ENDPOINT", Url::parse(&endpoint).map(Some));
+    let client_id = report.check(
+        "INTROSPECTION_CLIENT_ID",
+        config.var("INTROSPECTION_CLIENT_ID"),
+    );
+    let client_secret = report.check(
+        "INTROSPECTION_CLIENT_SECRET",
+        config.var("INTROSPECTION_CLIENT_SECRET"),
+    );
+    let mode = report.check(
+        "INTROSPECTION_MODE",
+        config.var("INTROSPECTION_MODE").unwrap_or_default().parse(),
+    );
+    let cache_size = report.check(
+        "INTROSPECTION_CACHE_SIZE",
+        config
+            .var("INTROSPECTION_CACHE_SIZE")
+            .unwrap_or("10000".to_string())
+            .parse::<usize>(),
+    );
+    let timeout = report.check(
+        "INTROSPECTION_TIMEOUT_MS",
+        get_millis(
+            config,
+            "INTROSPECTION_TIMEOUT_MS",
+            DEFAULT_INTROSPECTION_TIMEOUT,
+        ),
+    );
+
+    match endpoint {
+        Some(endpoint) => Introspection::new(
+            endpoint,
+            client_id,
+            client_secret,
+            mode,
+            cache_size,
+            timeout,
+        ),
+        None => Introspection::default(),
+    }
+}
+
+fn get_jwe_decryptor(config: &Config, report: &mut ConfigReport) -> JweDecryptor {
+    let mut jwe_decryptor = JweDecryptor::default();
+    if let Ok(keys_file) = config.var("JWE_PRIVATE_KEYS_FILE") {
+        if !keys_file.is_empty() {
+            jwe_decryptor.extend(report.check(
+                "JWE_PRIVATE_KEYS_FILE",
+                JweDecryptor::load_from_file(keys_file),
+            ));
+        }
+    }
+    if let Ok(keys) = config.var("JWE_PRIVATE_KEYS") {
+        if !keys.trim().is_empty() {
+            jwe_decryptor.extend(report.check("JWE_PRIVATE_KEYS", JweDecryptor::from_pem(&keys)));
+        }
+    }
+
+    jwe_decryptor
+}
+
+fn maybe_get_cel_self_test(config: &Config, report: &mut ConfigReport) -> Option<CelSelfTest> {
+    let mut self_test = CelSelfTest::default();
+    if let Ok(fixtures) = config.var("CEL_SELF_TEST_FIXTURES") {
+        if !fixtures.trim().is_empty() {
+            self_test.extend(report.check(
+                "CEL_SELF_TEST_FIXTURES",
+                CelSelfTest::load_from_str(&fixtures),
+            ));
+        }
+    }
+    if let Ok(fixtures_file) = config.var("CEL_SELF_TEST_FILE") {
+        if !fixtures_file.is_empty() {
+            self_test.extend(report.check(
+                "CEL_SELF_TEST_FILE",
+                CelSelfTest::load_from_file(fixtures_file),
+            ));
+        }
+    }
+
+    (!self_test.is_empty()).then_some(self_test)
+}
+
+/// Builds the handler from the configuration. Every invalid setting is reported.
+fn build_handler(config: &Config, telemetry: Telemetry) -> Result<handler::Handler, ConfigReport> {
+    let mut report = ConfigReport::default();
+
+    let jwks_uri = report.check(
+        "JWKS_URI",
+        config
+            .var("JWKS_URI")
+            .map_err(|e| e.to_string())
+            .and_then(|jwks_uri| {
+                jwks_uri
+                    .parse::<Url>()
+                    .map(Some)
+                    .map_err(|e| format!("Invalid value '{}': {}", jwks_uri, e))
+            }),
+    );
+    let min_refresh_rate = report.check(
+        "MIN_REFRESH_RATE",
+        get_seconds(config, "MIN_REFRESH_RATE", "900"),
+    );
 
-    let jwks_pre_cached_file_path = maybe_get_jwks_cache_path();
-    let principal_id_claims =
-        env::var("PRINCIPAL_ID_CLAIMS").unwrap_or("preferred_username, sub".to_string());
-    let default_principal_id = env::var("DEFAULT_PRINCIPAL_ID").unwrap_or("unknown".to_string());
-    let principal_id_claims = PrincipalIDClaims::from_comma_separated_values(
+    let jwks_pre_cached_file_path = maybe_get_jwks_cache_path(config);
+    let principal_id_claims = config.var_or("PRINCIPAL_ID_CLAIMS", "preferred_username, sub");
+    let default_principal_id = config.var_or("DEFAULT_PRINCIPAL_ID", "unknown");
+    let mut principal_id_claims = PrincipalIDClaims::from_comma_separated_values(
         principal_id_claims.as_str(),
         default_principal_id,
     );
-    let accepted_issuers = env::var("ACCEPTED_ISSUERS").unwrap_or_default();
+    let principal_id_cel = config.var("PRINCIPAL_ID_CEL").unwrap_or_default();
+    if !principal_id_cel.trim().is_empty() {
+        if let Some(cel) = report.check("PRINCIPAL_ID_CEL", principal_id_cel.parse().map(Some)) {
+            principal_id_claims = principal_id_claims.with_cel(cel);
+        }
+    }
+    let accepted_issuers = config.var("ACCEPTED_ISSUERS").unwrap_or_default();
     let accepted_issuers =
         AcceptedClaims::from_comma_separated_values(accepted_issuers.as_str(), "iss".to_string());
-    let accepted_audiences = env::var("ACCEPTED_AUDIENCES").unwrap_or_default();
+    let accepted_audiences = config.var("ACCEPTED_AUDIENCES").unwrap_or_default();
     let accepted_audiences: AcceptedClaims =
         AcceptedClaims::from_comma_separated_values(accepted_audiences.as_str(), "aud".to_string());
-    let accepted_signing_algorithms = env::var("ACCEPTED_ALGORITHMS").unwrap_or_default();
-    let accepted_signing_algorithms: AcceptedAlgorithms = accepted_signing_algorithms.parse()?; // infallible
-    let token_validation_cel = env::var("TOKEN_VALIDATION_CEL").unwrap_or_default();
-    let cel_validator: CelValidator = token_validation_cel.parse()?;
+    let accepted_signing_algorithms = config.var("ACCEPTED_ALGORITHMS").unwrap_or_default();
+    let accepted_signing_algorithms: AcceptedAlgorithms = report.check(
+        "ACCEPTED_ALGORITHMS",
+        accepted_signing_algorithms.parse(), // infallible
+    );
+    let token_validation_cel = config.var("TOKEN_VALIDATION_CEL").unwrap_or_default();
+    let mut cel_validator: CelValidator =
+        report.check("TOKEN_VALIDATION_CEL", token_validation_cel.parse());
+    let claim_constraints: ClaimConstraints = report.check(
+        "CLAIM_CONSTRAINTS",
+        config.var("CLAIM_CONSTRAINTS").unwrap_or_default().parse(),
+    );
+    cel_validator = cel_validator.with_rules(claim_constraints.into_rules());
+    if let Ok(rules_file) = config.var("TOKEN_VALIDATION_CEL_RULES_FILE") {
+        if !rules_file.is_empty() {
+            cel_validator = cel_validator.with_rules(report.check(
+                "TOKEN_VALIDATION_CEL_RULES_FILE",
+                CelRule::load_from_file(rules_file),
+            ));
+        }
+    }
+    let accepted_client_ids = config.var("ACCEPTED_CLIENT_IDS").unwrap_or_default();
+    let client_id_claims = config.var_or("CLIENT_ID_CLAIMS", DEFAULT_CLIENT_ID_CLAIMS);
+    let accepted_client_ids = report.check(
+        "CLIENT_ID_ISSUER_CLAIMS",
+        AcceptedClientIds::from_comma_separated_values(&accepted_client_ids, &client_id_claims)
+            .with_issuer_claims(&config.var("CLIENT_ID_ISSUER_CLAIMS").unwrap_or_default()),
+    );
+
+    let mut context_enrichment = ContextEnrichment::default();
+    let context_cel = config.var("CONTEXT_CEL").unwrap_or_default();
+    if !context_cel.trim().is_empty() {
+        if let Some(cel) = report.check("CONTEXT_CEL", context_cel.parse().map(Some)) {
+            context_enrichment = context_enrichment.with_cel(cel);
+        }
+    }
+    let context_claims = config.var("CONTEXT_CLAIMS").unwrap_or_default();
+    context_enrichment = context_enrichment
+        .with_claims(report.check(
+            "CONTEXT_CLAIMS",
+            ContextClaim::from_comma_separated_values(&context_claims),
+        ))
+        .with_array_separator(config.var_or("CONTEXT_ARRAY_SEPARATOR", ","))
+        .with_jwt_claims(
+            !config
+                .var("CONTEXT_JWT_CLAIMS")
+                .is_ok_and(|value| value.trim().eq_ignore_ascii_case("false")),
+        )
+        .with_tenant_key(config.var("CONTEXT_TENANT_KEY").unwrap_or_default());
+
+    let response_size_limit = config.var_or("RESPONSE_SIZE_LIMIT", "8192");
+    let response_size_reducible_fields = config
+        .var("RESPONSE_SIZE_REDUCIBLE_FIELDS")
+        .unwrap_or("jwtClaims".to_string());
+    let response_size_guard = ResponseSizeGuard::new(
+        report.check("RESPONSE_SIZE_LIMIT", response_size_limit.parse()),
+        report.check(
+            "RESPONSE_SIZE_REDUCIBLE_FIELDS",
+            ReducibleField::from_comma_separated_values(&response_size_reducible_fields),
+        ),
+    );
+
+    let rbac_routes_file = config
+        .var("RBAC_ROUTES_FILE")
+        .ok()
+        .filter(|path| !path.is_empty());
+    // roles are only extracted if they are configured or needed by the routes
+    let role_claims = match (config.var("ROLE_CLAIMS"), &rbac_routes_file) {
+        (Ok(role_claims), _) if !role_claims.trim().is_empty() => role_claims,
+        (_, Some(_)) => DEFAULT_ROLE_CLAIMS.to_string(),
+        _ => String::new(),
+    };
+    let rbac_routes = match rbac_routes_file {
+        Some(path) => report.check("RBAC_ROUTES_FILE", RbacRoute::load_from_file(path)),
+        None => Vec::new(),
+    };
+    let rbac = Rbac::from_comma_separated_values(&role_claims, rbac_routes)
+        .with_client_id(config.var("RBAC_CLIENT_ID").unwrap_or_default());
+
+    let usage_identifier_claim = config.var("USAGE_IDENTIFIER_KEY_CLAIM").unwrap_or_default();
+    let usage_identifier = UsageIdentifier::new(
+        Some(usage_identifier_claim.trim().to_string()).filter(|claim| !claim.is_empty()),
+        config
+            .var("USAGE_IDENTIFIER_KEY_REQUIRED")
+            .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true")),
+    );
+
+    let metrics_namespace = config.var("METRICS_NAMESPACE").unwrap_or_default();
+    let metrics_dimensions = config
+        .var("METRICS_DIMENSIONS")
+        .unwrap_or("Issuer, Stage".to_string());
+    let metrics = report.check(
+        "METRICS_DIMENSIONS",
+        Metrics::from_comma_separated_dimensions(metrics_namespace, &metrics_dimensions),
+    );
+
+    let log_redaction_level = report.check(
+        "LOG_REDACTION",
+        config.var("LOG_REDACTION").unwrap_or_default().parse(),
+    );
+    let log_claims_allowlist = config
+        .var("LOG_CLAIMS_ALLOWLIST")
+        .unwrap_or(DEFAULT_ALLOWED_CLAIMS.to_string());
+    let log_redactor =
+        LogRedactor::from_comma_separated_values(log_redaction_level, &log_claims_allowlist);
+
+    let audit_log = AuditLog::new(
+        config
+            .var("AUDIT_LOG")
+            .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true")),
+    );
 
-    tracing::init_default_subscriber();
+    let denylist = get_denylist(config, &mut report);
+
+    let introspection = get_introspection(config, &mut report);
+
+    let jwe_decryptor = get_jwe_decryptor(config, &mut report);
+
+    let dpop = get_dpop(config, &mut report);
+
+    let certificate_binding = CertificateBinding::new(
+        report.check(
+            "CERTIFICATE_BOUND_TOKENS",
+            config
+                .var("CERTIFICATE_BOUND_TOKENS")
+                .unwrap_or_default()
+                .parse(),
+        ),
+    );
+
+    let cel_self_test = maybe_get_cel_self_test(config, &mut report);
+
+    // the self-test is only meaningful if all the settings are valid
+    let Some(jwks_uri) = jwks_uri.filter(|_| !report.has_errors()) else {
+        return Err(report);
+    };
+    if let Some(cel_self_test) = cel_self_test {
+        if let Err(e) = cel_self_test.run(&cel_validator, &principal_id_claims, &context_enrichment)
+        {
+            report.error(None, e);
+            return Err(report);
+        }
+        tracing::info!("CEL self-test passed ({} fixtures)", cel_self_test.len());
+    }
 
     let keys = KeysStorage::new(jwks_uri, min_refresh_rate, jwks_pre_cached_file_path);
-    run(handler::Handler::new(
+    Ok(handler::Handler::new(
         Box::leak(Box::new(keys)),
         Box::leak(Box::new(principal_id_claims)),
         Box::leak(Box::new(accepted_issuers)),
         Box::leak(Box::new(accepted_audiences)),
         Box::leak(Box::new(accepted_signing_algorithms)),
         Box::leak(Box::new(cel_validator)),
-    ))
-    .await
+    )
+    .with_context_enrichment(Box::leak(Box::new(context_enrichment)))
+    .with_metrics(Box::leak(Box::new(metrics)))
+    .with_log_redactor(Box::leak(Box::new(log_redactor)))
+    .with_audit_log(Box::leak(Box::new(audit_log)))
+    .with_telemetry(Box::leak(Box::new(telemetry)))
+    .with_denylist(Box::leak(Box::new(denylist)))
+    .with_introspection(Box::leak(Box::new(introspection)))
+    .with_jwe_decryptor(Box::leak(Box::new(jwe_decryptor)))
+    .with_dpop(Box::leak(Box::new(dpop)))
+    .with_certificate_binding(Box::leak(Box::new(certificate_binding)))
+    .with_response_size_guard(Box::leak(Box::new(response_size_guard)))
+    .with_usage_identifier(Box::leak(Box::new(usage_identifier)))
+    .with_accepted_client_ids(Box::leak(Box::new(accepted_client_ids)))
+    .with_rbac(Box::leak(Box::new(rbac))))
+}
+
+/// Validates the configuration (including the handler settings) without starting the authorizer
+async fn check_config() -> ConfigReport {
+    let mut report = ConfigReport::default();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            report.error(Some("CONFIG_FILE"), e);
+            return report;
+        }
+    };
+    if let Err(errors) = build_handler(&config, Telemetry::default()) {
+        report.extend(errors);
+    }
+    report.extend(ConfigCheck::from_config(&config).run().await);
+    report
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    if env::args().skip(1).any(|arg| arg == "--check-config") {
+        let report = check_config().await;
+        println!("{}", report);
+        std::process::exit(if report.has_errors() { 1 } else { 0 });
+    }
+
+    let config = Config::load()?;
+    let telemetry = Telemetry::init()?;
+    let handler = build_handler(&config, telemetry)?;
+
+    if config
+        .var("STRICT_STARTUP")
+        .is_ok_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    {
+        let report = ConfigCheck::from_config(&config).run().await;
+        for issue in report.issues() {
+            let setting = issue.setting.unwrap_or_default();
+            match issue.severity {
+                IssueSeverity::Error => tracing::error!(setting, "{}", issue.message),
+                IssueSeverity::Warning => tracing::warn!(setting, "{}", issue.message),
+            }
+        }
+        if report.has_errors() {
+            return Err("Invalid configuration (STRICT_STARTUP is enabled)".into());
+        }
+    }
+
+    run(handler).await
 }
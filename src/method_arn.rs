@@ -0,0 +1,67 @@
+/// The components of an API Gateway `methodArn`
+/// (e.g. `arn:aws:execute-api:us-east-1:123456789012:apiId/stage/GET/some/path`).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MethodArn {
+    pub api_id: String,
+    pub stage: String,
+    pub method: String,
+    pub path: String,
+}
+
+impl MethodArn {
+    pub fn parse(method_arn: &str) -> Option<Self> {
+        // the resource part is everything after the 5th colon
+        let resource = method_arn.splitn(6, ':').nth(5)?;
+        let mut parts = resource.splitn(4, '/');
+        let api_id = parts.next()?.to_string();
+        let stage = parts.next()?.to_string();
+        let method = parts.next()?.to_string();
+        let path = format!("/{}", parts.next().unwrap_or_default());
+
+        Some(Self {
+            api_id,
+            stage,
+            method,
+            path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_a_method_arn() {
+        let arn = MethodArn::parse(
+            "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/prod/GET/pets/42",
+        );
+        assert_eq!(
+            arn,
+            Some(MethodArn {
+                api_id: "ymy8tbxw7b".to_string(),
+                stage: "prod".to_string(),
+                method: "GET".to_string(),
+                path: "/pets/42".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_should_parse_a_method_arn_for_the_root_path() {
+        let arn = MethodArn::parse("arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/*/GET/")
+            .unwrap();
+        assert_eq!(arn.stage, "*");
+        assert_eq!(arn.method, "GET");
+        assert_eq!(arn.path, "/");
+    }
+
+    #[test]
+    fn it_should_not_parse_invalid_method_arns() {
+        assert_eq!(MethodArn::parse("some_arn"), None);
+        assert_eq!(
+            MethodArn::parse("arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b"),
+            None
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::decision::Decision;
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    #[error("Invalid metrics dimension '{0}'. Supported dimensions: Issuer, Stage")]
+    InvalidDimension(String),
+}
+
+/// A dimension that can be attached to the emitted metrics
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MetricsDimension {
+    Issuer,
+    Stage,
+}
+
+impl MetricsDimension {
+    fn name(&self) -> &'static str {
+        match self {
+            MetricsDimension::Issuer => "Issuer",
+            MetricsDimension::Stage => "Stage",
+        }
+    }
+}
+
+impl FromStr for MetricsDimension {
+    type Err = MetricsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "issuer" => Ok(MetricsDimension::Issuer),
+            "stage" => Ok(MetricsDimension::Stage),
+            _ => Err(MetricsError::InvalidDimension(s.to_string())),
+        }
+    }
+}
+
+/// Emits metrics using the CloudWatch Embedded Metric Format (EMF).
+/// Metrics are disabled if no namespace is configured.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    namespace: Option<String>,
+    dimensions: Vec<MetricsDimension>,
+}
+
+impl Metrics {
+    pub fn new(namespace: String, dimensions: Vec<MetricsDimension>) -> Self {
+        Self {
+            namespace: (!namespace.is_empty()).then_some(namespace),
+            dimensions,
+        }
+    }
+
+    pub fn from_comma_separated_dimensions(
+        namespace: String,
+        comma_separated_dimensions: &str,
+    ) -> Result<Self, MetricsError> {
+        let dimensions = comma_separated_dimensions
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(MetricsDimension::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(namespace, dimensions))
+    }
+
+    /// Prints the EMF log line for the given decision to stdout (if metrics are enabled)
+    pub fn emit(&self, decision: &Decision) {
+        if let Some(emf) = self.to_emf(decision) {
+            println!("{}", emf);
+        }
+    }
+
+    pub fn to_emf(&self, decision: &Decision) -> Option<Value> {
+        let namespace = self.namespace.as_ref()?;
+
+        let dimension_names: Vec<&str> = self.dimensions.iter().map(|d| d.name()).collect();
+        let mut directives = vec![json!({
+            "Namespace": namespace,
+            "Dimensions": [dimension_names],
+            "Metrics": [
+                { "Name": "Allow", "Unit": "Count" },
+                { "Name": "Deny", "Unit": "Count" },
+                { "Name": "JwksRefreshes", "Unit": "Count" },
+                { "Name": "JwksRefreshFailures", "Unit": "Count" },
+                { "Name": "PreWarmedCacheMisses", "Unit": "Count" },
+                { "Name": "Latency", "Unit": "Milliseconds" },
+            ],
+        })];
+        if decision.deny_reason.is_some() {
+            // deny counts are also broken down by reason
+            let mut reason_dimension_names = dimension_names.clone();
+            reason_dimension_names.push("DenyReason");
+            directives.push(json!({
+                "Namespace": namespace,
+                "Dimensions": [reason_dimension_names],
+                "Metrics": [{ "Name": "Deny", "Unit": "Count" }],
+            }));
+        }
+
+        let mut emf = Map::new();
+        emf.insert(
+            "_aws".to_string(),
+            json!({
+                "Timestamp": Utc::now().timestamp_millis(),
+                "CloudWatchMetrics": directives,
+            }),
+        );
+        for dimension in &self.dimensions {
+            let value = match dimension {
+                MetricsDimension::Issuer => decision.issuer.as_deref(),
+                MetricsDimension::Stage => Some(decision.stage.as_str()),
+            }
+            .filter(|v| !v.is_empty())
+            .unwrap_or("unknown");
+            emf.insert(dimension.name().to_string(), json!(value));
+        }
+        if let Some(reason) = &decision.deny_reason {
+            emf.insert("DenyReason".to_string(), json!(reason.name()));
+        }
+        let denied = decision.deny_reason.is_some() as u64;
+        emf.insert("Allow".to_string(), json!(1 - denied));
+        emf.insert("Deny".to_string(), json!(denied));
+        emf.insert("JwksRefreshes".to_string(), json!(decision.jwks.refreshes));
+        emf.insert(
+            "JwksRefreshFailures".to_string(),
+            json!(decision.jwks.refresh_failures),
+        );
+        emf.insert(
+            "PreWarmedCacheMisses".to_string(),
+            json!(decision.jwks.pre_warmed_cache_misses),
+        );
+        emf.insert(
+            "Latency".to_string(),
+            json!(decision.latency.as_secs_f64() * 1000.0),
+        );
+
+        Some(Value::Object(emf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decision::DenyReason, keys_storage::KeysStorageStats};
+    use std::time::Duration;
+
+    #[test]
+    fn it_should_not_emit_metrics_without_a_namespace() {
+        let metrics = Metrics::default();
+        assert!(metrics.to_emf(&Decision::default()).is_none());
+        let metrics = Metrics::new("".to_string(), vec![MetricsDimension::Stage]);
+        assert!(metrics.to_emf(&Decision::default()).is_none());
+    }
+
+    #[test]
+    fn it_should_parse_dimensions() {
+        let metrics =
+            Metrics::from_comma_separated_dimensions("ns".to_string(), "issuer, Stage").unwrap();
+        assert_eq!(
+            metrics.dimensions,
+            vec![MetricsDimension::Issuer, MetricsDimension::Stage]
+        );
+        assert!(Metrics::from_comma_separated_dimensions("ns".to_string(), "region").is_err());
+    }
+
+    #[test]
+    fn it_should_create_an_emf_entry_for_an_allowed_request() {
+        let metrics =
+            Metrics::from_comma_separated_dimensions("OidcAuthorizer".to_string(), "Issuer, Stage")
+                .unwrap();
+        let emf = metrics
+            .to_emf(&Decision {
+                deny_reason: None,
+                issuer: Some("https://example.com".to_string()),
+                stage: "prod".to_string(),
+                latency: Duration::from_millis(12),
+                jwks: KeysStorageStats {
+                    refreshes: 1,
+                    refresh_failures: 0,
+                    pre_warmed_cache_misses: 1,
+                },
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert!(emf["_aws"]["Timestamp"].is_i64());
+        let directives = emf["_aws"]["CloudWatchMetrics"].as_array().unwrap();
+        assert_eq!(directives.len(), 1);
+        assert_eq!(directives[0]["Namespace"], "OidcAuthorizer");
+        assert_eq!(directives[0]["Dimensions"], json!([["Issuer", "Stage"]]));
+        assert_eq!(emf["Issuer"], "https://example.com");
+        assert_eq!(emf["Stage"], "prod");
+        assert_eq!(emf["Allow"], 1);
+        assert_eq!(emf["Deny"], 0);
+        assert_eq!(emf["JwksRefreshes"], 1);
+        assert_eq!(emf["JwksRefreshFailures"], 0);
+        assert_eq!(emf["PreWarmedCacheMisses"], 1);
+        assert_eq!(emf["Latency"], 12.0);
+        assert!(emf.get("DenyReason").is_none());
+    }
+
+    #[test]
+    fn it_should_create_an_emf_entry_for_a_denied_request() {
+        let metrics =
+            Metrics::from_comma_separated_dimensions("OidcAuthorizer".to_string(), "Issuer")
+                .unwrap();
+        let emf = metrics
+            .to_emf(&Decision {
+                deny_reason: Some(DenyReason::MalformedScheme),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let directives = emf["_aws"]["CloudWatchMetrics"].as_array().unwrap();
+        assert_eq!(directives.len(), 2);
+        assert_eq!(
+            directives[1]["Dimensions"],
+            json!([["Issuer", "DenyReason"]])
+        );
+        assert_eq!(
+            directives[1]["Metrics"],
+            json!([{ "Name": "Deny", "Unit": "Count" }])
+        );
+        assert_eq!(emf["Issuer"], "unknown");
+        assert_eq!(emf["DenyReason"], "MalformedScheme");
+        assert_eq!(emf["Allow"], 0);
+        assert_eq!(emf["Deny"], 1);
+    }
+}
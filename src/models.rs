@@ -1,13 +1,160 @@
+use crate::method_arn::MethodArn;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
-#[derive(Clone, Debug, Deserialize)]
+/// An API Gateway authorizer event.
+/// TOKEN events only carry `authorizationToken` and `methodArn`, while REQUEST events
+/// carry the request metadata (headers, path, method, stage, etc.) instead.
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct TokenAuthorizerEvent {
-    #[serde(rename = "authorizationToken")]
+    #[serde(rename = "authorizationToken", default)]
     pub authorization_token: String,
     #[serde(rename = "methodArn")]
     pub method_arn: String,
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(rename = "multiValueHeaders", default)]
+    pub multi_value_headers: Option<HashMap<String, Vec<String>>>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(rename = "httpMethod", default)]
+    pub http_method: Option<String>,
+    #[serde(rename = "stageVariables", default)]
+    pub stage_variables: Option<HashMap<String, String>>,
+    #[serde(rename = "requestContext", default)]
+    pub request_context: Option<RequestContext>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RequestContext {
+    #[serde(default)]
+    pub stage: Option<String>,
+    #[serde(rename = "domainName", default)]
+    pub domain_name: Option<String>,
+    /// The full request path (including the stage or the base path of a custom domain)
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub identity: Option<RequestIdentity>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RequestIdentity {
+    #[serde(rename = "sourceIp", default)]
+    pub source_ip: Option<String>,
+    /// The client certificate (only available if mutual TLS is enabled)
+    #[serde(rename = "clientCert", default)]
+    pub client_cert: Option<ClientCert>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClientCert {
+    #[serde(rename = "clientCertPem")]
+    pub client_cert_pem: String,
+}
+
+/// The request metadata exposed to CEL expressions as the `request` variable.
+/// Header names are lowercased. Values that are not available (e.g. headers in TOKEN events)
+/// are left empty.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RequestMetadata {
+    pub headers: HashMap<String, String>,
+    #[serde(rename = "sourceIp")]
+    pub source_ip: String,
+    pub path: String,
+    pub method: String,
+    pub stage: String,
+    #[serde(rename = "stageVariables")]
+    pub stage_variables: HashMap<String, String>,
+    /// The tenant captured by the `{tenant}` placeholder of the accepted issuer (or audience) patterns
+    pub tenant: String,
+    /// The roles extracted from the token claims (see `ROLE_CLAIMS`)
+    pub roles: Vec<String>,
+}
+
+impl TokenAuthorizerEvent {
+    /// Returns the value of the given header (case-insensitive), if present.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .as_ref()?
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns all the values of the given header (case-insensitive).
+    /// For REQUEST events, repeated headers are only available in `multiValueHeaders`.
+    pub fn header_values(&self, name: &str) -> Vec<&str> {
+        match &self.multi_value_headers {
+            Some(headers) => headers
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+                .flat_map(|(_, v)| v.iter().map(String::as_str))
+                .collect(),
+            None => self.header(name).into_iter().collect(),
+        }
+    }
+
+    /// Returns the URL of the request (without query string), if it's a REQUEST event
+    pub fn request_url(&self) -> Option<String> {
+        let request_context = self.request_context.as_ref();
+        let host = request_context
+            .and_then(|c| c.domain_name.as_deref())
+            .or_else(|| self.header("host"))?;
+        let path = request_context
+            .and_then(|c| c.path.as_deref())
+            .or(self.path.as_deref())?;
+        Some(format!("https://{}{}", host, path))
+    }
+
+    /// Returns the PEM encoded client certificate (mutual TLS), if present
+    pub fn client_cert_pem(&self) -> Option<&str> {
+        self.request_context
+            .as_ref()?
+            .identity
+            .as_ref()?
+            .client_cert
+            .as_ref()
+            .map(|cert| cert.client_cert_pem.as_str())
+    }
+
+    /// Returns the raw authorization header value.
+    /// For REQUEST events this falls back to the `Authorization` header.
+    pub fn authorization_header(&self) -> &str {
+        if !self.authorization_token.is_empty() {
+            return &self.authorization_token;
+        }
+        self.header("authorization").unwrap_or_default()
+    }
+
+    pub fn request_metadata(&self) -> RequestMetadata {
+        let method_arn = MethodArn::parse(&self.method_arn).unwrap_or_default();
+        let request_context = self.request_context.as_ref();
+
+        RequestMetadata {
+            headers: self
+                .headers
+                .iter()
+                .flatten()
+                .map(|(k, v)| (k.to_lowercase(), v.clone()))
+                .collect(),
+            source_ip: request_context
+                .and_then(|c| c.identity.as_ref())
+                .and_then(|i| i.source_ip.clone())
+                .unwrap_or_default(),
+            path: self.path.clone().unwrap_or(method_arn.path),
+            method: self.http_method.clone().unwrap_or(method_arn.method),
+            stage: request_context
+                .and_then(|c| c.stage.clone())
+                .unwrap_or(method_arn.stage),
+            stage_variables: self.stage_variables.clone().unwrap_or_default(),
+            // only known after the token has been validated
+            tenant: String::new(),
+            roles: Vec::new(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -34,7 +181,10 @@ pub struct TokenAuthorizerResponse {
     pub principal_id: String,
     #[serde(rename = "policyDocument")]
     pub policy_document: PolicyDocument,
-    pub context: HashMap<String, String>,
+    pub context: HashMap<String, Value>,
+    /// The API key used by usage plans (if the API key source is `AUTHORIZER`)
+    #[serde(rename = "usageIdentifierKey", skip_serializing_if = "Option::is_none")]
+    pub usage_identifier_key: Option<String>,
 }
 
 impl TokenAuthorizerResponse {
@@ -43,11 +193,12 @@ impl TokenAuthorizerResponse {
         let mut context = HashMap::new();
         context.insert(
             "jwtClaims".to_string(),
-            serde_json::to_string(token_claims).unwrap(),
+            Value::String(serde_json::to_string(token_claims).unwrap()),
         );
 
         Self {
             context,
+            usage_identifier_key: None,
             principal_id: principal_id.to_string(),
             policy_document: PolicyDocument {
                 version: "2012-10-17".to_string(),
@@ -63,10 +214,38 @@ impl TokenAuthorizerResponse {
         }
     }
 
+    /// Restricts the allow policy to the given resource (e.g. the method ARN of the request).
+    /// Needed when the decision depends on the route, since API Gateway caches the policy by token.
+    pub fn for_resource(mut self, resource: &str) -> Self {
+        for statement in &mut self.policy_document.statement {
+            statement.resource = resource.to_string();
+        }
+        self
+    }
+
+    /// Removes the whole token payload (`jwtClaims`) from the response context
+    pub fn without_jwt_claims(mut self) -> Self {
+        self.context.remove("jwtClaims");
+        self
+    }
+
+    /// Adds extra values to the response context
+    pub fn with_context(mut self, context: HashMap<String, Value>) -> Self {
+        self.context.extend(context);
+        self
+    }
+
+    /// Sets the API key used by usage plans
+    pub fn with_usage_identifier_key(mut self, usage_identifier_key: Option<String>) -> Self {
+        self.usage_identifier_key = usage_identifier_key;
+        self
+    }
+
     #[inline]
     pub fn deny(resource: &str) -> Self {
         Self {
             context: HashMap::new(),
+            usage_identifier_key: None,
             principal_id: "none".to_string(),
             policy_document: PolicyDocument {
                 version: "2012-10-17".to_string(),
@@ -85,6 +264,91 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn it_should_extract_request_metadata_from_a_token_event() {
+        let event: TokenAuthorizerEvent = serde_json::from_value(json!({
+            "type": "TOKEN",
+            "authorizationToken": "Bearer sometoken",
+            "methodArn": "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/prod/GET/pets"
+        }))
+        .unwrap();
+        assert_eq!(event.authorization_header(), "Bearer sometoken");
+        assert_eq!(
+            event.request_metadata(),
+            RequestMetadata {
+                path: "/pets".to_string(),
+                method: "GET".to_string(),
+                stage: "prod".to_string(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_extract_request_metadata_from_a_request_event() {
+        let event: TokenAuthorizerEvent = serde_json::from_value(json!({
+            "type": "REQUEST",
+            "methodArn": "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/prod/GET/pets",
+            "path": "/pets",
+            "httpMethod": "GET",
+            "headers": { "Authorization": "Bearer sometoken", "X-Tenant": "acme" },
+            "stageVariables": null,
+            "requestContext": {
+                "stage": "prod",
+                "identity": {
+                    "sourceIp": "10.0.0.1",
+                    "clientCert": {
+                        "clientCertPem": "-----BEGIN CERTIFICATE-----...",
+                        "subjectDN": "CN=test-client"
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(event.authorization_header(), "Bearer sometoken");
+        assert_eq!(
+            event.client_cert_pem(),
+            Some("-----BEGIN CERTIFICATE-----...")
+        );
+        let metadata = event.request_metadata();
+        assert_eq!(metadata.headers.get("x-tenant").unwrap(), "acme");
+        assert_eq!(metadata.source_ip, "10.0.0.1");
+        assert_eq!(metadata.path, "/pets");
+        assert_eq!(metadata.method, "GET");
+        assert_eq!(metadata.stage, "prod");
+        assert!(metadata.stage_variables.is_empty());
+    }
+
+    #[test]
+    fn it_should_extract_the_request_url_and_repeated_headers() {
+        let event: TokenAuthorizerEvent = serde_json::from_value(json!({
+            "type": "REQUEST",
+            "methodArn": "arn:aws:execute-api:us-east-1:123456789012:ymy8tbxw7b/prod/GET/pets",
+            "path": "/pets",
+            "httpMethod": "GET",
+            "headers": { "Host": "ymy8tbxw7b.execute-api.us-east-1.amazonaws.com", "DPoP": "proof-2" },
+            "multiValueHeaders": { "DPoP": ["proof-1", "proof-2"] },
+            "requestContext": {
+                "stage": "prod",
+                "domainName": "api.example.com",
+                "path": "/v1/pets"
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            event.request_url().unwrap(),
+            "https://api.example.com/v1/pets"
+        );
+        assert_eq!(event.header_values("dpop"), vec!["proof-1", "proof-2"]);
+
+        let event = TokenAuthorizerEvent {
+            headers: Some(HashMap::from([("DPoP".to_string(), "proof".to_string())])),
+            ..Default::default()
+        };
+        assert_eq!(event.request_url(), None);
+        assert_eq!(event.header_values("dpop"), vec!["proof"]);
+    }
+
     #[test]
     fn it_should_create_an_allow_response() {
         let principal_id = "John Doe";
@@ -115,6 +379,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_should_add_extra_values_to_the_context() {
+        let token_claims = json!({"sub": "1234567890"});
+        let response = TokenAuthorizerResponse::allow("John Doe", &token_claims)
+            .with_context(HashMap::from([("tenant".to_string(), json!("acme"))]));
+        assert_eq!(
+            response.context,
+            HashMap::from([
+                ("jwtClaims".to_string(), json!("{\"sub\":\"1234567890\"}")),
+                ("tenant".to_string(), json!("acme")),
+            ])
+        );
+    }
+
     #[test]
     fn it_create_a_deny_response() {
         let resource = "arn::some:resource";
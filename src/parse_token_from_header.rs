@@ -1,8 +1,25 @@
-static PARSE_ERROR_MESSAGE: &str = "Authorization token must start with 'Bearer '";
+static PARSE_ERROR_MESSAGE: &str = "Authorization token must start with 'Bearer ' or 'DPoP '";
 
-pub fn parse_token_from_header(authorization_token: &str) -> Result<&str, &'static str> {
-    if authorization_token.len() >= 8 && &(authorization_token[0..7]) == "Bearer " {
-        return Ok(&(authorization_token[7..]));
+/// The authorization scheme used to present the token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScheme {
+    Bearer,
+    /// A DPoP-bound token (RFC 9449), sent together with a `DPoP` proof header
+    DPoP,
+}
+
+pub fn parse_token_from_header(
+    authorization_token: &str,
+) -> Result<(TokenScheme, &str), &'static str> {
+    for (scheme, prefix) in [
+        (TokenScheme::Bearer, "Bearer "),
+        (TokenScheme::DPoP, "DPoP "),
+    ] {
+        if let Some(token) = authorization_token.strip_prefix(prefix) {
+            if !token.is_empty() {
+                return Ok((scheme, token));
+            }
+        }
     }
     Err(PARSE_ERROR_MESSAGE)
 }
@@ -15,7 +32,14 @@ mod tests {
     fn it_should_parse_a_token_from_a_valid_header() {
         let result = parse_token_from_header("Bearer sometoken");
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "sometoken");
+        assert_eq!(result.unwrap(), (TokenScheme::Bearer, "sometoken"));
+    }
+
+    #[test]
+    fn it_should_parse_a_dpop_token() {
+        let result = parse_token_from_header("DPoP sometoken");
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), (TokenScheme::DPoP, "sometoken"));
     }
 
     #[test]
@@ -1,9 +1,19 @@
+use crate::{
+    cel_validation::{CelExpression, CelValidationError},
+    claim_path,
+    models::RequestMetadata,
+};
+use jsonwebtoken::Header;
 use serde_json::Value;
 
+/// The claims used (in order) to compute the principal ID.
+/// Every field can be a claim name, a dotted path (`ext.user.email`, `groups.0`),
+/// a JSON Pointer (`/ext/user/email`) or a template (`{iss}|{sub}`).
 #[derive(Debug, Clone, Default)]
 pub struct PrincipalIDClaims {
     fields: Vec<String>,
     default_value: String,
+    cel: Option<CelExpression>,
 }
 
 impl PrincipalIDClaims {
@@ -11,9 +21,49 @@ impl PrincipalIDClaims {
         Self {
             fields,
             default_value,
+            cel: None,
         }
     }
 
+    /// Uses a CEL expression to compute the principal ID.
+    /// The claims are still used (in order) if the expression returns `null` or an empty string.
+    pub fn with_cel(mut self, cel: CelExpression) -> Self {
+        self.cel = Some(cel);
+        self
+    }
+
+    /// Returns `true` if the principal ID depends on the request (and not only on the token)
+    pub fn is_request_dependent(&self) -> bool {
+        self.cel
+            .as_ref()
+            .is_some_and(CelExpression::is_request_dependent)
+    }
+
+    pub fn get_principal_id(
+        &self,
+        header: Option<&Header>,
+        claims: &Value,
+        request: &RequestMetadata,
+    ) -> Result<String, CelValidationError> {
+        if let Some(cel) = &self.cel {
+            match cel.evaluate(header, claims, request)? {
+                Value::Null => {}
+                Value::String(s) if s.is_empty() => {}
+                Value::String(s) => return Ok(s),
+                value @ (Value::Number(_) | Value::Bool(_)) => return Ok(value.to_string()),
+                value => {
+                    return Err(CelValidationError::UnexpectedResult(format!(
+                        "the principal ID expression '{}' must return a string (got '{}')",
+                        cel.expression(),
+                        value
+                    )))
+                }
+            }
+        }
+
+        Ok(self.get_principal_id_from_claims(claims))
+    }
+
     pub fn from_comma_separated_values(
         comma_separated_values: &str,
         default_value: String,
@@ -29,11 +79,12 @@ impl PrincipalIDClaims {
 
     pub fn get_principal_id_from_claims(&self, claims: &Value) -> String {
         for field in &self.fields {
-            if let Some(claim_value) = claims.get(field) {
-                return claim_value
-                    .as_str()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| claim_value.to_string());
+            let principal_id = match field.contains('{') {
+                true => claim_path::render(field, claims),
+                false => claim_path::lookup(claims, field).map(claim_path::to_string),
+            };
+            if let Some(principal_id) = principal_id {
+                return principal_id;
             }
         }
 
@@ -68,6 +119,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_should_get_the_principal_id_from_nested_claims_and_templates() {
+        let principal_id_claims = PrincipalIDClaims::from_comma_separated_values(
+            "/ext/user/email, ext.user.id, groups.0, {iss}|{sub}",
+            "some_default".to_string(),
+        );
+        assert_eq!(
+            principal_id_claims.get_principal_id_from_claims(
+                &json!({"ext": {"user": {"email": "user@example.com", "id": "u1"}}})
+            ),
+            "user@example.com"
+        );
+        assert_eq!(
+            principal_id_claims
+                .get_principal_id_from_claims(&json!({"ext": {"user": {"id": "u1"}}})),
+            "u1"
+        );
+        assert_eq!(
+            principal_id_claims.get_principal_id_from_claims(&json!({"groups": ["a", "b"]})),
+            "a"
+        );
+        assert_eq!(
+            principal_id_claims.get_principal_id_from_claims(
+                &json!({"iss": "https://idp.example.com", "sub": "user123"})
+            ),
+            "https://idp.example.com|user123"
+        );
+        // templates only match if all the claims are present
+        assert_eq!(
+            principal_id_claims.get_principal_id_from_claims(&json!({"sub": "user123"})),
+            "some_default"
+        );
+    }
+
     #[test]
     fn it_should_get_fallback_to_the_default_value_if_all_the_expected_claims_are_missing() {
         let principal_id_claims =
@@ -77,4 +162,53 @@ mod tests {
             "some_default"
         );
     }
+
+    #[test]
+    fn it_should_get_the_principal_id_from_a_cel_expression() {
+        let principal_id_claims =
+            PrincipalIDClaims::from_comma_separated_values("sub", "some_default".to_string())
+                .with_cel(r#"claims.tenant + ":" + claims.sub"#.parse().unwrap());
+        assert_eq!(
+            principal_id_claims
+                .get_principal_id(
+                    Some(&Header::default()),
+                    &json!({"sub": "user123", "tenant": "acme"}),
+                    &RequestMetadata::default()
+                )
+                .unwrap(),
+            "acme:user123"
+        );
+    }
+
+    #[test]
+    fn it_should_fall_back_to_the_claims_if_the_cel_expression_returns_null() {
+        let principal_id_claims =
+            PrincipalIDClaims::from_comma_separated_values("sub", "some_default".to_string())
+                .with_cel("has(claims.email) ? claims.email : null".parse().unwrap());
+        assert_eq!(
+            principal_id_claims
+                .get_principal_id(
+                    Some(&Header::default()),
+                    &json!({"sub": "user123"}),
+                    &RequestMetadata::default()
+                )
+                .unwrap(),
+            "user123"
+        );
+    }
+
+    #[test]
+    fn it_should_fail_if_the_cel_expression_does_not_return_a_string() {
+        let principal_id_claims =
+            PrincipalIDClaims::from_comma_separated_values("sub", "some_default".to_string())
+                .with_cel("[claims.sub]".parse().unwrap());
+        assert!(matches!(
+            principal_id_claims.get_principal_id(
+                Some(&Header::default()),
+                &json!({"sub": "user123"}),
+                &RequestMetadata::default()
+            ),
+            Err(CelValidationError::UnexpectedResult(_))
+        ));
+    }
 }
@@ -0,0 +1,344 @@
+use crate::glob_pattern::route_match;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use thiserror::Error;
+
+/// The claims where the most common identity providers put roles and groups
+pub static DEFAULT_ROLE_CLAIMS: &str =
+    "groups, cognito:groups, realm_access.roles, resource_access.{client}.roles, roles";
+
+/// A role claim segment replaced by the client ID the roles are read for
+/// (e.g. `resource_access.{client}.roles`)
+pub static CLIENT_PLACEHOLDER: &str = "{client}";
+
+/// The RBAC routes file cannot be loaded (at startup)
+#[derive(Debug, Error)]
+#[error("Failed to load RBAC routes file: {0}")]
+pub struct RbacRoutesFileError(String);
+
+#[derive(Debug, Error)]
+pub enum RbacError {
+    #[error("Missing required roles for route '{route}' (expected {expected})")]
+    AccessDenied { route: String, expected: String },
+}
+
+/// Whether a route requires any or all of its roles
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleMatch {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RbacRoutesFile {
+    routes: Vec<RbacRoute>,
+}
+
+/// The roles required to call a route. Routes are glob patterns in the form
+/// `"<METHOD> <path>"` (e.g. `"GET /admin/*"`) or just `"<path>"` to match any method.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RbacRoute {
+    pub route: String,
+    pub roles: Vec<String>,
+    #[serde(default, rename = "match")]
+    pub role_match: RoleMatch,
+}
+
+impl RbacRoute {
+    /// Loads a list of routes from a JSON or YAML file
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Vec<Self>, RbacRoutesFileError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| RbacRoutesFileError(format!("'{}': {}", path.display(), e)))?;
+        Self::load_from_str(&content)
+    }
+
+    /// Parses a list of routes from a JSON or YAML string (YAML is a superset of JSON)
+    pub fn load_from_str(content: &str) -> Result<Vec<Self>, RbacRoutesFileError> {
+        let routes_file: RbacRoutesFile =
+            serde_yaml::from_str(content).map_err(|e| RbacRoutesFileError(e.to_string()))?;
+        Ok(routes_file.routes)
+    }
+
+    fn is_satisfied_by(&self, roles: &[String]) -> bool {
+        let has_role = |role: &String| roles.contains(role);
+        match self.role_match {
+            RoleMatch::Any => self.roles.iter().any(has_role),
+            RoleMatch::All => self.roles.iter().all(has_role),
+        }
+    }
+}
+
+/// Collects the strings found at the given path. A `*` segment matches every
+/// value of an object (e.g. `resource_access.*.roles`).
+fn collect_strings<'a>(value: &'a Value, segments: &[&str], result: &mut Vec<&'a str>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        match value {
+            Value::String(s) => result.extend(s.split([' ', ',']).filter(|s| !s.is_empty())),
+            Value::Array(items) => result.extend(items.iter().filter_map(Value::as_str)),
+            _ => {}
+        }
+        return;
+    };
+    match (*segment, value) {
+        ("*", Value::Object(map)) => map
+            .values()
+            .for_each(|value| collect_strings(value, rest, result)),
+        (segment, value) => {
+            if let Some(value) = value.get(segment) {
+                collect_strings(value, rest, result)
+            }
+        }
+    }
+}
+
+/// Role based access control: normalizes the roles found in the token claims
+/// and checks them against a route table
+#[derive(Debug, Clone, Default)]
+pub struct Rbac {
+    role_claims: Vec<String>,
+    routes: Vec<RbacRoute>,
+    client_id: Option<String>,
+}
+
+impl Rbac {
+    pub fn new(role_claims: Vec<String>, routes: Vec<RbacRoute>) -> Self {
+        Self {
+            role_claims,
+            routes,
+            client_id: None,
+        }
+    }
+
+    /// Sets the client ID replacing the `{client}` segment of the role claims. If empty, the
+    /// `azp` claim of the token is used.
+    pub fn with_client_id(mut self, client_id: String) -> Self {
+        self.client_id = Some(client_id.trim().to_string()).filter(|id| !id.is_empty());
+        self
+    }
+
+    pub fn from_comma_separated_values(
+        comma_separated_values: &str,
+        routes: Vec<RbacRoute>,
+    ) -> Self {
+        Self::new(
+            comma_separated_values
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            routes,
+        )
+    }
+
+    /// Checks if any route requires roles, in which case the decision depends on the request
+    pub fn has_routes(&self) -> bool {
+        !self.routes.is_empty()
+    }
+
+    /// Extracts the roles (deduplicated) from the configured claims. Claims can be arrays
+    /// or strings (separated by spaces or commas). Claims with a `{client}` segment are only
+    /// read for the configured client ID (or the `azp` of the token), so that the roles
+    /// granted by other clients are ignored.
+    pub fn roles(&self, claims: &Value) -> Vec<String> {
+        let client_id = self.client_id.as_deref().or_else(|| claims["azp"].as_str());
+        let mut roles = Vec::new();
+        for role_claim in &self.role_claims {
+            // claims whose name contains dots take precedence over nested paths
+            match claims.get(role_claim) {
+                Some(value) => collect_strings(value, &[], &mut roles),
+                None => {
+                    let segments: Option<Vec<&str>> = role_claim
+                        .split('.')
+                        .map(|segment| match segment == CLIENT_PLACEHOLDER {
+                            true => client_id,
+                            false => Some(segment),
+                        })
+                        .collect();
+                    if let Some(segments) = segments {
+                        collect_strings(claims, &segments, &mut roles)
+                    }
+                }
+            }
+        }
+
+        let mut unique_roles: Vec<String> = Vec::with_capacity(roles.len());
+        for role in roles {
+            if !unique_roles.iter().any(|r| r == role) {
+                unique_roles.push(role.to_string());
+            }
+        }
+        unique_roles
+    }
+
+    /// Checks the roles against the first route matching the request.
+    /// Requests not matching any route are allowed.
+    pub fn check(&self, roles: &[String], method: &str, path: &str) -> Result<(), RbacError> {
+        let Some(route) = self
+            .routes
+            .iter()
+            .find(|route| route_match(&route.route, method, path))
+        else {
+            return Ok(());
+        };
+
+        if !route.is_satisfied_by(roles) {
+            return Err(RbacError::AccessDenied {
+                route: route.route.clone(),
+                expected: format!(
+                    "{} of [{}]",
+                    match route.role_match {
+                        RoleMatch::Any => "any",
+                        RoleMatch::All => "all",
+                    },
+                    route.roles.join(", ")
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn routes() -> Vec<RbacRoute> {
+        RbacRoute::load_from_str(
+            r#"
+routes:
+  - route: "GET /admin/reports/*"
+    roles: [admin, auditor]
+  - route: "/admin/*"
+    roles: [admin, superuser]
+    match: all
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_should_load_routes() {
+        assert_eq!(
+            routes(),
+            vec![
+                RbacRoute {
+                    route: "GET /admin/reports/*".to_string(),
+                    roles: vec!["admin".to_string(), "auditor".to_string()],
+                    role_match: RoleMatch::Any,
+                },
+                RbacRoute {
+                    route: "/admin/*".to_string(),
+                    roles: vec!["admin".to_string(), "superuser".to_string()],
+                    role_match: RoleMatch::All,
+                },
+            ]
+        );
+        assert!(matches!(
+            RbacRoute::load_from_str("routes: [{route: '/admin', roles: [admin], other: 1}]"),
+            Err(RbacRoutesFileError(_))
+        ));
+    }
+
+    #[test]
+    fn it_should_normalize_the_roles() {
+        let rbac = Rbac::from_comma_separated_values(DEFAULT_ROLE_CLAIMS, vec![]);
+        assert_eq!(
+            rbac.roles(&json!({
+                "groups": ["admin", "users"],
+                "cognito:groups": ["users", "support"],
+                "realm_access": { "roles": ["offline_access"] },
+                "resource_access": {
+                    "api": { "roles": ["reader"] },
+                    "account": { "roles": ["manage-account"] }
+                },
+                "azp": "api",
+                "roles": "writer,editor"
+            })),
+            vec![
+                "admin",
+                "users",
+                "support",
+                "offline_access",
+                "reader",
+                "writer",
+                "editor"
+            ]
+        );
+        assert!(rbac.roles(&json!({"sub": "user123"})).is_empty());
+
+        let rbac = Rbac::from_comma_separated_values("resource_access.api.roles", vec![]);
+        assert_eq!(
+            rbac.roles(&json!({"resource_access": { "api": { "roles": ["reader"] }, "other": { "roles": ["writer"] } }})),
+            vec!["reader"]
+        );
+    }
+
+    #[test]
+    fn it_should_only_read_the_client_roles_of_the_caller() {
+        let claims = json!({
+            "azp": "web-app",
+            "resource_access": {
+                "web-app": { "roles": ["viewer"] },
+                "admin-console": { "roles": ["admin"] }
+            }
+        });
+        let routes =
+            RbacRoute::load_from_str(r#"{"routes": [{"route": "/admin/*", "roles": ["admin"]}]}"#)
+                .unwrap();
+
+        // the admin role of another client is not granted to the caller
+        let rbac = Rbac::from_comma_separated_values(DEFAULT_ROLE_CLAIMS, routes.clone());
+        let roles = rbac.roles(&claims);
+        assert_eq!(roles, vec!["viewer"]);
+        assert!(matches!(
+            rbac.check(&roles, "GET", "/admin/users"),
+            Err(RbacError::AccessDenied { .. })
+        ));
+
+        // the configured client ID takes precedence over the azp claim
+        let rbac = Rbac::from_comma_separated_values(DEFAULT_ROLE_CLAIMS, routes)
+            .with_client_id("admin-console".to_string());
+        let roles = rbac.roles(&claims);
+        assert_eq!(roles, vec!["admin"]);
+        assert!(rbac.check(&roles, "GET", "/admin/users").is_ok());
+
+        // without a client ID, client roles are ignored
+        let rbac = Rbac::from_comma_separated_values(DEFAULT_ROLE_CLAIMS, vec![]);
+        assert!(rbac
+            .roles(&json!({"resource_access": { "web-app": { "roles": ["viewer"] } }}))
+            .is_empty());
+    }
+
+    #[test]
+    fn it_should_check_the_roles_against_the_routes() {
+        let rbac = Rbac::from_comma_separated_values(DEFAULT_ROLE_CLAIMS, routes());
+        let roles = |roles: &[&str]| roles.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+
+        // any
+        assert!(rbac
+            .check(&roles(&["auditor"]), "GET", "/admin/reports/2024")
+            .is_ok());
+        // all
+        assert!(rbac
+            .check(&roles(&["admin", "superuser"]), "DELETE", "/admin/users")
+            .is_ok());
+        assert!(matches!(
+            rbac.check(&roles(&["admin"]), "DELETE", "/admin/users"),
+            Err(RbacError::AccessDenied { route, .. }) if route == "/admin/*"
+        ));
+        // the first matching route is used
+        assert!(matches!(
+            rbac.check(&roles(&["superuser"]), "GET", "/admin/reports/2024"),
+            Err(RbacError::AccessDenied { route, .. }) if route == "GET /admin/reports/*"
+        ));
+        // routes without rules are allowed
+        assert!(rbac.check(&[], "GET", "/pets").is_ok());
+    }
+}
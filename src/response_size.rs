@@ -0,0 +1,256 @@
+use crate::models::TokenAuthorizerResponse;
+use serde_json::Value;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ResponseSizeError {
+    #[error("Invalid response size field '{0}'. Expected format: 'contextKey[:drop|trim]'")]
+    Malformed(String),
+    #[error("The authorizer response is {size} bytes, which exceeds the limit of {limit} bytes")]
+    TooLarge { size: usize, limit: usize },
+}
+
+/// How a context field is reduced when the response is too large
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ReduceAction {
+    /// The field is removed from the context
+    #[default]
+    Drop,
+    /// The field is truncated to fit the limit (and removed if that's not enough)
+    Trim,
+}
+
+/// A context field that can be reduced when the response is too large
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReducibleField {
+    key: String,
+    action: ReduceAction,
+}
+
+impl FromStr for ReducibleField {
+    type Err = ResponseSizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, action) = s.split_once(':').unwrap_or((s, ""));
+        let action = match action.trim().to_lowercase().as_str() {
+            "" | "drop" => ReduceAction::Drop,
+            "trim" => ReduceAction::Trim,
+            _ => return Err(ResponseSizeError::Malformed(s.to_string())),
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(ResponseSizeError::Malformed(s.to_string()));
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+            action,
+        })
+    }
+}
+
+impl ReducibleField {
+    pub fn from_comma_separated_values(
+        comma_separated_values: &str,
+    ) -> Result<Vec<Self>, ResponseSizeError> {
+        comma_separated_values
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+fn response_size(response: &TokenAuthorizerResponse) -> usize {
+    serde_json::to_string(response)
+        .map(|s| s.len())
+        .unwrap_or_default()
+}
+
+/// Keeps the authorizer response within the limits accepted by API Gateway.
+/// Context values must be strings, numbers or booleans, and the serialized response
+/// (policy + context) must not exceed the configured size.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseSizeGuard {
+    /// The maximum size (in bytes) of the serialized response (0 disables the size check)
+    limit: usize,
+    /// The context fields that can be reduced, in order
+    fields: Vec<ReducibleField>,
+}
+
+impl ResponseSizeGuard {
+    pub fn new(limit: usize, fields: Vec<ReducibleField>) -> Self {
+        Self { limit, fields }
+    }
+
+    pub fn enforce(
+        &self,
+        mut response: TokenAuthorizerResponse,
+    ) -> Result<TokenAuthorizerResponse, ResponseSizeError> {
+        // nested values are not supported by API Gateway
+        response.context.retain(|_, value| !value.is_null());
+        for value in response.context.values_mut() {
+            if value.is_array() || value.is_object() {
+                *value = Value::String(value.to_string());
+            }
+        }
+
+        if self.limit == 0 {
+            return Ok(response);
+        }
+
+        let mut size = response_size(&response);
+        let mut reduced = Vec::new();
+        for field in &self.fields {
+            if size <= self.limit {
+                break;
+            }
+            if field.action == ReduceAction::Trim {
+                // the serialized value is at least as long as the raw value (escaping),
+                // so removing the excess bytes might take more than one iteration
+                let mut trimmed = false;
+                while size > self.limit {
+                    let Some(Value::String(value)) = response.context.get_mut(&field.key) else {
+                        break;
+                    };
+                    if value.is_empty() {
+                        break;
+                    }
+                    let mut len = value.len().saturating_sub(size - self.limit);
+                    while !value.is_char_boundary(len) {
+                        len -= 1;
+                    }
+                    value.truncate(len);
+                    trimmed = true;
+                    size = response_size(&response);
+                }
+                if trimmed && size <= self.limit {
+                    reduced.push(format!("{} (trimmed)", field.key));
+                    continue;
+                }
+            }
+            if response.context.remove(&field.key).is_some() {
+                reduced.push(field.key.clone());
+                size = response_size(&response);
+            }
+        }
+
+        if !reduced.is_empty() {
+            tracing::warn!(
+                "The authorizer response exceeded {} bytes. Reduced context fields: {}",
+                self.limit,
+                reduced.join(", ")
+            );
+        }
+        if size > self.limit {
+            return Err(ResponseSizeError::TooLarge {
+                size,
+                limit: self.limit,
+            });
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tracing_test::traced_test;
+
+    fn make_response(context: Value) -> TokenAuthorizerResponse {
+        TokenAuthorizerResponse::allow("some_user", &json!({"sub": "some_user"}))
+            .without_jwt_claims()
+            .with_context(serde_json::from_value::<HashMap<String, Value>>(context).unwrap())
+    }
+
+    #[test]
+    fn it_should_parse_reducible_fields() {
+        let fields =
+            ReducibleField::from_comma_separated_values("jwtClaims, groups:trim, ").unwrap();
+        assert_eq!(
+            fields,
+            vec![
+                ReducibleField {
+                    key: "jwtClaims".to_string(),
+                    action: ReduceAction::Drop,
+                },
+                ReducibleField {
+                    key: "groups".to_string(),
+                    action: ReduceAction::Trim,
+                },
+            ]
+        );
+        assert!(ReducibleField::from_comma_separated_values("groups:shrink").is_err());
+        assert!(ReducibleField::from_comma_separated_values(":trim").is_err());
+    }
+
+    #[test]
+    fn it_should_convert_nested_values() {
+        let guard = ResponseSizeGuard::default();
+        let response = guard
+            .enforce(make_response(json!({
+                "roles": ["admin", "user"],
+                "address": {"country": "IT"},
+                "missing": null,
+                "level": 3,
+            })))
+            .unwrap();
+        assert_eq!(
+            response.context,
+            HashMap::from([
+                ("roles".to_string(), json!("[\"admin\",\"user\"]")),
+                ("address".to_string(), json!("{\"country\":\"IT\"}")),
+                ("level".to_string(), json!(3)),
+            ])
+        );
+    }
+
+    #[test]
+    #[traced_test]
+    fn it_should_reduce_the_configured_fields() {
+        let context = json!({
+            "userId": "some_user",
+            "jwtClaims": "x".repeat(1000),
+            "groups": "group-".repeat(200),
+        });
+        let limit = response_size(&make_response(json!({"userId": "some_user"}))) + 100;
+        let guard = ResponseSizeGuard::new(
+            limit,
+            ReducibleField::from_comma_separated_values("jwtClaims, groups:trim").unwrap(),
+        );
+
+        let response = guard.enforce(make_response(context.clone())).unwrap();
+        assert!(response_size(&response) <= limit);
+        assert!(!response.context.contains_key("jwtClaims"));
+        assert!(response.context["groups"]
+            .as_str()
+            .unwrap()
+            .starts_with("group-group-"));
+        assert_eq!(response.context["userId"], "some_user");
+        assert!(logs_contain(
+            "Reduced context fields: jwtClaims, groups (trimmed)"
+        ));
+
+        // responses within the limit are not changed
+        let guard = ResponseSizeGuard::new(
+            10_000,
+            ReducibleField::from_comma_separated_values("jwtClaims").unwrap(),
+        );
+        let response = guard.enforce(make_response(context.clone())).unwrap();
+        assert!(response.context.contains_key("jwtClaims"));
+
+        // if the configured fields are not enough, the response is rejected
+        let guard = ResponseSizeGuard::new(
+            limit,
+            ReducibleField::from_comma_separated_values("jwtClaims").unwrap(),
+        );
+        assert!(matches!(
+            guard.enforce(make_response(context)),
+            Err(ResponseSizeError::TooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,267 @@
+use lambda_runtime::{tracing, Error};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::trace::SdkTracerProvider;
+
+/// Sets up logging and (when the `otlp` feature is enabled and an OTLP endpoint is configured)
+/// the export of tracing spans to an OpenTelemetry collector.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Initializes the global tracing subscriber
+    #[cfg(not(feature = "otlp"))]
+    pub fn init() -> Result<Self, Error> {
+        tracing::init_default_subscriber();
+        Ok(Self::default())
+    }
+
+    /// Initializes the global tracing subscriber. Spans are exported using OTLP over HTTP
+    /// if `OTEL_EXPORTER_OTLP_ENDPOINT` (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set.
+    #[cfg(feature = "otlp")]
+    pub fn init() -> Result<Self, Error> {
+        use std::env;
+
+        let otlp_enabled = [
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+        ]
+        .iter()
+        .any(|name| env::var(name).is_ok_and(|value| !value.trim().is_empty()));
+        if !otlp_enabled {
+            tracing::init_default_subscriber();
+            return Ok(Self::default());
+        }
+
+        let provider = otlp::build_tracer_provider(None)?;
+        otlp::init_subscriber(&provider);
+        Ok(Self {
+            provider: Some(provider),
+        })
+    }
+
+    /// Makes the trace of the invocation (taken from its X-Ray trace header) the parent of
+    /// `span`, so that the exported spans show up in the same trace as the rest of the request.
+    pub fn set_xray_parent(span: &tracing::Span, trace_header: Option<&str>) {
+        #[cfg(feature = "otlp")]
+        if let Some(parent) = trace_header.and_then(otlp::xray_parent_context) {
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+            if let Err(e) = span.set_parent(parent) {
+                tracing::debug!("Unable to set the X-Ray parent of the span: {}", e);
+            }
+        }
+        #[cfg(not(feature = "otlp"))]
+        let _ = (span, trace_header);
+    }
+
+    /// Exports all the pending spans. This needs to happen at the end of every invocation
+    /// since the Lambda execution environment might be frozen right after.
+    pub fn flush(&self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = &self.provider {
+            if let Err(e) = provider.force_flush() {
+                tracing::warn!("Failed to export tracing spans: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use lambda_runtime::Error;
+    use opentelemetry::{
+        trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider,
+        },
+        Context,
+    };
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+    use std::{env, str::FromStr};
+    use tracing_subscriber::{
+        filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+        Registry,
+    };
+
+    /// Creates a tracer provider exporting spans over OTLP/HTTP. If no endpoint is provided,
+    /// it's taken from the standard `OTEL_EXPORTER_OTLP_*` environment variables.
+    pub fn build_tracer_provider(endpoint: Option<String>) -> Result<SdkTracerProvider, Error> {
+        let mut exporter = SpanExporter::builder().with_http();
+        if let Some(endpoint) = endpoint {
+            exporter = exporter.with_endpoint(endpoint);
+        }
+
+        Ok(SdkTracerProvider::builder()
+            .with_batch_exporter(exporter.build()?)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .build())
+    }
+
+    pub fn otel_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+            .with_filter(LevelFilter::INFO)
+    }
+
+    /// Extracts the remote parent from an X-Ray trace header
+    /// (e.g. `Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1`).
+    /// Same as the `XrayPropagator` of `opentelemetry-aws`, which is not compatible with the
+    /// OpenTelemetry version in use.
+    pub fn xray_parent_context(trace_header: &str) -> Option<Context> {
+        let mut trace_id = None;
+        let mut span_id = None;
+        let mut trace_flags = TraceFlags::default();
+        for part in trace_header.split(';') {
+            match part.trim().split_once('=')? {
+                ("Root", root) => {
+                    let (version, rest) = root.split_once('-')?;
+                    let (epoch, unique_id) = rest.split_once('-')?;
+                    if version != "1" || epoch.len() != 8 || unique_id.len() != 24 {
+                        return None;
+                    }
+                    trace_id = TraceId::from_hex(&format!("{epoch}{unique_id}")).ok();
+                }
+                ("Parent", parent) if parent.len() == 16 => {
+                    span_id = SpanId::from_hex(parent).ok();
+                }
+                ("Sampled", "1") => trace_flags = TraceFlags::SAMPLED,
+                _ => {}
+            }
+        }
+
+        let span_context = SpanContext::new(
+            trace_id?,
+            span_id?,
+            trace_flags,
+            true,
+            TraceState::default(),
+        );
+        span_context
+            .is_valid()
+            .then(|| Context::new().with_remote_span_context(span_context))
+    }
+
+    /// Same as `lambda_runtime::tracing::init_default_subscriber()` with the addition of the
+    /// OpenTelemetry layer (which is not affected by the log level)
+    pub fn init_subscriber(provider: &SdkTracerProvider) {
+        let log_format = env::var("AWS_LAMBDA_LOG_FORMAT").unwrap_or_default();
+        let log_level = env::var("AWS_LAMBDA_LOG_LEVEL").or_else(|_| env::var("RUST_LOG"));
+        let log_level = LevelFilter::from_str(log_level.as_deref().unwrap_or("INFO"))
+            .unwrap_or(LevelFilter::INFO);
+        let env_filter = EnvFilter::builder()
+            .with_default_directive(log_level.into())
+            .from_env_lossy();
+
+        let fmt_layer = tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .without_time();
+        let fmt_layer = if log_format.eq_ignore_ascii_case("json") {
+            fmt_layer.json().with_filter(env_filter).boxed()
+        } else {
+            fmt_layer.with_filter(env_filter).boxed()
+        };
+
+        Registry::default()
+            .with(fmt_layer)
+            .with(otel_layer(provider))
+            .init();
+    }
+}
+
+#[cfg(all(test, feature = "otlp"))]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use opentelemetry::trace::{SpanId, TraceContextExt, TraceId};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const XRAY_TRACE_HEADER: &str =
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn it_should_export_spans_to_an_otlp_collector() {
+        let server = MockServer::start();
+        let collector_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/traces")
+                .header("content-type", "application/x-protobuf")
+                .is_true(|req| {
+                    // the spans belong to the X-Ray trace and the root span has the X-Ray parent
+                    contains(
+                        req.body_ref(),
+                        &TraceId::from_hex("5759e988bd862e3fe1be46a994272793")
+                            .unwrap()
+                            .to_bytes(),
+                    ) && contains(
+                        req.body_ref(),
+                        &SpanId::from_hex("53995c3f42cd8ad8").unwrap().to_bytes(),
+                    )
+                });
+            then.status(200);
+        });
+
+        let provider = otlp::build_tracer_provider(Some(server.url("/v1/traces"))).unwrap();
+        let subscriber = tracing_subscriber::registry().with(otlp::otel_layer(&provider));
+        ::tracing::subscriber::with_default(subscriber, || {
+            let span =
+                ::tracing::info_span!("authorizer.authorize", faas.invocation_id = "some-id");
+            Telemetry::set_xray_parent(&span, Some(XRAY_TRACE_HEADER));
+            span.in_scope(|| ::tracing::info_span!("token.verify").in_scope(|| {}));
+        });
+
+        let telemetry = Telemetry {
+            provider: Some(provider),
+        };
+        telemetry.flush();
+
+        collector_mock.assert();
+    }
+
+    #[test]
+    fn it_should_extract_the_parent_from_an_xray_trace_header() {
+        let parent = otlp::xray_parent_context(XRAY_TRACE_HEADER).unwrap();
+        let span_context = parent.span().span_context().clone();
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("5759e988bd862e3fe1be46a994272793").unwrap()
+        );
+        assert_eq!(
+            span_context.span_id(),
+            SpanId::from_hex("53995c3f42cd8ad8").unwrap()
+        );
+        assert!(span_context.is_sampled());
+        assert!(span_context.is_remote());
+
+        let parent = otlp::xray_parent_context(
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=0",
+        )
+        .unwrap();
+        assert!(!parent.span().span_context().is_sampled());
+
+        for header in [
+            "",
+            "Root=1-5759e988-bd862e3fe1be46a994272793",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=xyz;Sampled=1",
+            "Root=2-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1",
+            "Root=1-5759e988bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1",
+        ] {
+            assert!(otlp::xray_parent_context(header).is_none(), "{header}");
+        }
+    }
+}
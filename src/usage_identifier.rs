@@ -0,0 +1,85 @@
+use crate::claim_path;
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum UsageIdentifierError {
+    #[error("The token does not contain the usage identifier claim '{0}'")]
+    MissingClaim(String),
+}
+
+/// Selects the claim used as `usageIdentifierKey` (the API key of API Gateway usage plans,
+/// when the API key source is `AUTHORIZER`)
+#[derive(Debug, Clone, Default)]
+pub struct UsageIdentifier {
+    claim: Option<String>,
+    required: bool,
+}
+
+impl UsageIdentifier {
+    pub fn new(claim: Option<String>, required: bool) -> Self {
+        Self { claim, required }
+    }
+
+    pub fn get_usage_identifier_key(
+        &self,
+        claims: &Value,
+    ) -> Result<Option<String>, UsageIdentifierError> {
+        let Some(claim) = &self.claim else {
+            return Ok(None);
+        };
+        match claim_path::lookup(claims, claim) {
+            Some(Value::Null) | None if self.required => {
+                Err(UsageIdentifierError::MissingClaim(claim.clone()))
+            }
+            Some(Value::Null) | None => Ok(None),
+            Some(value) => Ok(Some(claim_path::to_string(value))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_should_get_the_usage_identifier_key() {
+        let claims = json!({"client_id": "client-1", "ext": {"api_key": "key-1"}});
+
+        let usage_identifier = UsageIdentifier::default();
+        assert_eq!(
+            usage_identifier.get_usage_identifier_key(&claims).unwrap(),
+            None
+        );
+
+        let usage_identifier = UsageIdentifier::new(Some("client_id".to_string()), false);
+        assert_eq!(
+            usage_identifier.get_usage_identifier_key(&claims).unwrap(),
+            Some("client-1".to_string())
+        );
+
+        let usage_identifier = UsageIdentifier::new(Some("ext.api_key".to_string()), true);
+        assert_eq!(
+            usage_identifier.get_usage_identifier_key(&claims).unwrap(),
+            Some("key-1".to_string())
+        );
+    }
+
+    #[test]
+    fn it_should_fail_if_a_required_claim_is_missing() {
+        let claims = json!({"sub": "user123"});
+
+        let usage_identifier = UsageIdentifier::new(Some("api_key".to_string()), false);
+        assert_eq!(
+            usage_identifier.get_usage_identifier_key(&claims).unwrap(),
+            None
+        );
+
+        let usage_identifier = UsageIdentifier::new(Some("api_key".to_string()), true);
+        assert!(matches!(
+            usage_identifier.get_usage_identifier_key(&claims),
+            Err(UsageIdentifierError::MissingClaim(_))
+        ));
+    }
+}